@@ -1,6 +1,6 @@
 // A static import is required in b/g scripts because they are executed in their own env
 // not connected to the content scripts where wasm is loaded automatically
-import initWasmModule, { hello_wasm, add_random_tracks } from './wasm/wasm_mod.js';
+import initWasmModule, { hello_wasm, add_random_tracks, cancel_current_job, resume_job } from './wasm/wasm_mod.js';
 
 console.log("Background script started");
 // console.log(await chrome.permissions.getAll());
@@ -15,11 +15,18 @@ let userDetailsRequestHeaders = new Headers(); // a copy of Spotify headers to i
 // a temp flag to stop multiple fetches
 let fetching = false;
 
+// set if the last job stopped before it finished, e.g. the browser terminated this script or the token expired
+// the job is resumed as soon as the creds are captured because WASM cannot call Spotify without them
+let resumePending = false;
+
 // run the wasm initializer before calling wasm methods
 // the initializer is generated by wasm_pack
 (async () => {
     await initWasmModule();
     hello_wasm(); // this call logs a hello message from WASM for demo purposes
+
+    await checkForUnfinishedJob();
+    resumeJobIfPending();
 })();
 
 // A placeholder for OnSuccess in .then
@@ -42,8 +49,19 @@ function onErrorWithLog(error) {
 chrome.runtime.onMessage.addListener(async (request, sender, sendResponse) => {
     // console.log(`Popup message received: ${JSON.stringify(request)}, ${JSON.stringify(sender)}`);
 
+    // cancelling goes around the check for a running job below because it is only useful while a job is running
+    if (request?.action == "btn_cancel") {
+        console.log("User clicked btn_cancel");
+        if (fetching) {
+            // WASM stops before its next request and reports what it has written via the usual progress messages
+            cancel_current_job();
+        }
+        return;
+    }
+
     // check what kind of message it is - act on it or log it if the msg cannot be understood
     let numberOfTracksToAdd = 500; // default value
+    let options = undefined; // WASM uses the defaults for undefined
     if (request?.action == "btn_add") {
         numberOfTracksToAdd = Number(request?.qty); // Number() cast is required because WASM wrapper asserts types and expects a number for Rust's u32
         // this is a check for the main action - let the code run its course after the completion of this if-block
@@ -53,6 +71,7 @@ chrome.runtime.onMessage.addListener(async (request, sender, sendResponse) => {
             chrome.runtime.sendMessage("Missing how many tracks to add param. It's a bug.").then(onSuccess, onError);
             return;
         }
+        options = request?.options;
     }
     else {
         // this is an unexpected option - something is off or there is a bug
@@ -64,7 +83,7 @@ chrome.runtime.onMessage.addListener(async (request, sender, sendResponse) => {
     // only one wasm should be running at a time
     // TODO: disable the button
     if (fetching) {
-        chrome.runtime.sendMessage("Already running. Restart the browser if stuck on this message.").then(onSuccess, onError);
+        chrome.runtime.sendMessage("Already running. Click Stop to cancel it.").then(onSuccess, onError);
         return;
     }
 
@@ -89,35 +108,70 @@ chrome.runtime.onMessage.addListener(async (request, sender, sendResponse) => {
     }
 
     // call the WASM code
-    if (authHeaderValue && tokenHeaderValue && !fetching) {
-
-        // indicate an active WASM process 
-        fetching = true;
-
-        toggleToolbarBadge();
-
-        // remove the listener because there will be a lot of requests from WASM
-        // it makes no sense to intercept them for token extraction
-        // there is a small chance the token changes while WASM is running
-        chrome.webRequest.onBeforeSendHeaders.removeListener(captureSessionToken);
-
-        // call WASM
-        add_random_tracks(authHeaderValue, tokenHeaderValue, playlistId, userUri, numberOfTracksToAdd)
-            .catch((e) => {
-                console.error(e);
-                chrome.runtime.sendMessage(JSON.stringify(e)).then(onSuccess, onError);
-            })
-            .finally(() => {
-                // reset WASM, log to inactive and drop toolbar icon badge
-                fetching = false;
-                // restore the listener to capture any token changes in between WASM runs
-                chrome.webRequest.onBeforeSendHeaders.addListener(captureSessionToken, { urls: ['https://api-partner.spotify.com/pathfinder/v1/query*'] }, ["requestHeaders"])
-                // restore the toolbar badge that signals to the popup that WASM is no longer running
-                toggleToolbarBadge();
-            })
-    }
+    // the options are passed as is, WASM checks them and reports what is wrong
+    runWasm(() => add_random_tracks(authHeaderValue, tokenHeaderValue, playlistId, userUri, numberOfTracksToAdd, options));
 });
 
+/// Runs a WASM job if the creds are there and no other job is running
+/// wasmCall is a function that starts the job and returns its promise
+function runWasm(wasmCall) {
+    if (!authHeaderValue || !tokenHeaderValue || fetching) {
+        return;
+    }
+
+    // indicate an active WASM process 
+    fetching = true;
+
+    toggleToolbarBadge();
+
+    // remove the listener because there will be a lot of requests from WASM
+    // it makes no sense to intercept them for token extraction
+    // there is a small chance the token changes while WASM is running
+    chrome.webRequest.onBeforeSendHeaders.removeListener(captureSessionToken);
+
+    // call WASM
+    wasmCall()
+        .catch((e) => {
+            console.error(e);
+            chrome.runtime.sendMessage(JSON.stringify(e)).then(onSuccess, onError);
+        })
+        .finally(async () => {
+            // WASM keeps the job state only if the job may succeed on another attempt
+            await checkForUnfinishedJob();
+            // reset WASM, log to inactive and drop toolbar icon badge
+            fetching = false;
+            // restore the listener to capture any token changes in between WASM runs
+            chrome.webRequest.onBeforeSendHeaders.addListener(captureSessionToken, { urls: ['https://api-partner.spotify.com/pathfinder/v1/query*'] }, ["requestHeaders"])
+            // restore the toolbar badge that signals to the popup that WASM is no longer running
+            toggleToolbarBadge();
+        })
+}
+
+/// Sets resumePending if WASM left the state of an unfinished job in the storage
+async function checkForUnfinishedJob() {
+    // WASM saves the state of a running job under this key and removes it when the job is done
+    try {
+        const values = await chrome.storage.local.get("jobState");
+        resumePending = !!values?.jobState;
+    }
+    catch (e) {
+        console.error(`Cannot read jobState: ${e}`);
+    }
+    console.log(`Unfinished job: ${resumePending}`);
+}
+
+/// Resumes the unfinished job once the creds are captured
+/// WASM discards the job after a few failed attempts, so this does not go on forever
+function resumeJobIfPending() {
+    if (!resumePending || !authHeaderValue || !tokenHeaderValue || fetching) {
+        return;
+    }
+
+    resumePending = false;
+    console.log("Resuming the unfinished job");
+    runWasm(() => resume_job(authHeaderValue, tokenHeaderValue));
+}
+
 /// Sets the badge as per fetching var and notifies the popup about the status change
 /// When the popup window is loaded, it checks if the badge is set and presumes that the WASM script is running
 function toggleToolbarBadge() {
@@ -161,6 +215,7 @@ async function captureSessionToken(requestDetails) {
         userDetailsRequestHeaders = headers;
         // console.log(`Tokens captured: ${authHeaderValue} / ${tokenHeaderValue}`)
         // console.log(requestDetails.requestHeaders)
+        resumeJobIfPending();
     }
     else {
         // console.log(`Tokens missing: ${auth} / ${token} / ${requestDetails.requestHeaders}`)
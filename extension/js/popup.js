@@ -10,6 +10,68 @@ function onError(error) {
   console.error(`Promise error: ${error}`);
 }
 
+// The options in popup.html have the same IDs as the fields WASM expects
+const OPTION_CHECKBOXES = ["includeLikedSongs", "includeAlbums", "includePlaylists", "ownPlaylistsOnly"];
+const OPTION_NUMBERS = ["likedSongsSharePct", "tracksPerAlbum", "maxAlbums", "tracksPerPlaylist", "maxTracksPerPlaylist", "maxPlaylists", "sourceWeightCap", "seed"];
+const OPTION_SELECTS = ["sourcePolicy"];
+// the fields of options.sourceMix
+const MIX_NUMBERS = ["likedSongsPct", "albumsPct", "playlistsPct"];
+
+// Returns the options from the popup as an object for WASM.
+// Empty fields are left out for WASM to use the defaults. WASM validates the values and reports what is wrong.
+function readOptions() {
+  const options = {};
+  for (const id of OPTION_CHECKBOXES) {
+    options[id] = document.getElementById(id).checked;
+  }
+  for (const id of OPTION_NUMBERS) {
+    const value = document.getElementById(id).value;
+    if (value !== "") {
+      options[id] = Number(value);
+    }
+  }
+  for (const id of OPTION_SELECTS) {
+    options[id] = document.getElementById(id).value;
+  }
+
+  // the mix is used only if any of its fields is set, the empty ones are 0%
+  if (MIX_NUMBERS.some((id) => document.getElementById(id).value !== "")) {
+    options.sourceMix = {};
+    for (const id of MIX_NUMBERS) {
+      options.sourceMix[id] = Number(document.getElementById(id).value);
+    }
+  }
+
+  // IDs can be copied from the share links, so commas, spaces and line breaks all work as separators
+  const excludeSources = document.getElementById("excludeSources").value.split(/[\s,]+/).filter((v) => v);
+  if (excludeSources.length) {
+    options.excludeSources = excludeSources;
+  }
+
+  return options;
+}
+
+// Puts the options saved by readOptions() back into the popup
+function showOptions(options) {
+  for (const id of OPTION_CHECKBOXES) {
+    if (typeof options?.[id] === "boolean") {
+      document.getElementById(id).checked = options[id];
+    }
+  }
+  for (const id of OPTION_NUMBERS) {
+    document.getElementById(id).value = options?.[id] ?? "";
+  }
+  for (const id of OPTION_SELECTS) {
+    if (options?.[id]) {
+      document.getElementById(id).value = options[id];
+    }
+  }
+  for (const id of MIX_NUMBERS) {
+    document.getElementById(id).value = options?.sourceMix?.[id] ?? "";
+  }
+  document.getElementById("excludeSources").value = options?.excludeSources?.join(", ") ?? "";
+}
+
 // Popups cannot have any inline scripts with our security policies.
 // Click handlers should be added when the popup is opened.
 document.addEventListener('DOMContentLoaded', async function () {
@@ -59,7 +121,10 @@ document.addEventListener('DOMContentLoaded', async function () {
       // console.log("Permission decided");
       if (await chrome.permissions.contains(permissions)) {
         // console.log("Permission granted");
-        await chrome.runtime.sendMessage({ action: "btn_add", qty: document.getElementById("tracks_qty").value });
+        const options = readOptions();
+        // the popup is recreated every time it is opened, so the options are kept in the storage
+        chrome.storage.local.set({ generationOptions: options }).then(onSuccess, onError);
+        await chrome.runtime.sendMessage({ action: "btn_add", qty: document.getElementById("tracks_qty").value, options: options });
         document.getElementById("btn_add").disabled = true;
       }
       else {
@@ -71,10 +136,20 @@ document.addEventListener('DOMContentLoaded', async function () {
     });
   });
 
+  // cancel button, only enabled while WASM is running
+  document.getElementById("btn_cancel").addEventListener("click", async () => {
+    console.log("btn_cancel button clicked");
+    await chrome.runtime.sendMessage({ action: "btn_cancel" });
+  });
+
+  // restore the options from the last run
+  chrome.storage.local.get("generationOptions").then((values) => showOptions(values?.generationOptions), onError);
+
   chrome.action.getBadgeText({}).then((badgeText) => {
     // console.log(`Badge: ${badgeText}`);
     if (badgeText) {
       btn.disabled = true;
+      document.getElementById("btn_cancel").disabled = false;
       document.getElementById("log-summary").innerText = "Waiting for progress update ..."
     };
   }, onError)
@@ -97,6 +172,7 @@ chrome.runtime.onMessage.addListener((msg, sender) => {
   // there is no badge change event
   if (typeof msg === "boolean") {
     document.getElementById("btn_add").disabled = msg;
+    document.getElementById("btn_cancel").disabled = !msg;
     return;
   }
 
@@ -1,25 +1,30 @@
 use crate::{
     constants,
     constants::log,
-    models,
+    hash_registry::HashRegistry,
     models::{
-        IgnoredData, Payload, PayloadExtensions, PersistedQuery, VariablesAddTracksToPlaylist,
-        VariablesAlbumOrPlaylistTracks, VariablesV3Items,
+        generation_options::LibraryOrder,
+        operations::{AddToPlaylist, FetchLibraryTracks, FetchPlaylist, GetAlbum, LibraryV3},
+        playlist::PlaylistV2,
+        InsertPosition, NewPosition, Payload, VariablesAddTracksToPlaylist,
+        VariablesAlbumOrPlaylistTracks, VariablesLikedTracks, VariablesV3Items,
     },
-    BrowserRuntime, RetryAfter,
+    transport::Transport,
+    Error, Result,
 };
 
 mod utils;
-use utils::{build_get_url, execute_http_request, BUILD_POST_URL};
+use utils::{execute_with_retry, fetch_pages, BUILD_POST_URL};
 
-/// Returns IDs of all album tracks.
-pub(crate) async fn fetch_album_tracks(
+/// Returns IDs of all album tracks, up to `max_number_of_tracks` or all of them if None.
+pub(crate) async fn fetch_album_tracks<T: Transport>(
     auth_header_value: &str,
     token_header_value: &str,
     album_id: &str,
-    max_number_of_tracks: usize,
-    runtime: &BrowserRuntime,
-) -> Vec<String> {
+    max_number_of_tracks: Option<usize>,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<Vec<String>> {
     log!("fetch_album_tracks for: {album_id}");
 
     // request examples
@@ -28,55 +33,23 @@ pub(crate) async fn fetch_album_tracks(
     // variables: {"uri":"spotify:album:4kWmZoSHYtMxvejnCwv3ID","locale":"","offset":0,"limit":50}
     // extensions: {"persistedQuery":{"version":1,"sha256Hash":"46ae954ef2d2fe7732b4b2b4022157b2e18b7ea84f70591ceb164e4de1b5d5d3"}}
 
-    // get the first page of albums to see how many there are
-    let mut variables = VariablesAlbumOrPlaylistTracks {
+    let variables = VariablesAlbumOrPlaylistTracks {
         uri: [constants::ID_PREFIX_ALBUM, album_id].concat(),
         ..Default::default()
     };
 
-    let mut url = match build_get_url(
-        constants::operations::ALBUM_TRACKS,
-        &variables,
-        constants::persistent_queries::GET_ALBUM,
-    ) {
-        Ok(v) => v,
-        Err(_) => return Vec::new(),
-    };
-
     // get the list of album tracks from Spotify
-    let album_tracks = match execute_http_request::<
-        models::album::AlbumTracksRoot,
-        Option<IgnoredData>,
-    >(auth_header_value, token_header_value, &url, None, runtime)
-    .await
-    {
-        Ok(v) => v,
-        Err(e) if matches!(e, RetryAfter::Never) => {
-            return Vec::new();
-        }
-        Err(_) => {
-            unimplemented!("Retries are not implemented");
-        }
-    };
-
-    // log!("{:?}", lib_v3_items);
+    let (_, items) = fetch_pages::<GetAlbum, _>(
+        auth_header_value,
+        token_header_value,
+        hashes,
+        variables,
+        max_number_of_tracks,
+        transport,
+    )
+    .await?;
 
-    // get the number of lib_v3_items and calculate the number of pages that can be downloaded
-    let total_track_count = album_tracks.data.album_union.tracks.total_count;
-    let total_pages = total_track_count / constants::ITEMS_PER_PAGE;
-    let total_pages = if total_track_count % constants::ITEMS_PER_PAGE > 0 {
-        total_track_count / constants::ITEMS_PER_PAGE + 1
-    } else {
-        total_pages
-    };
-    log!("Tracks: {total_track_count}, pages: {total_pages}");
-
-    // a collection of all albums from all pages
-    let mut album_tracks = album_tracks
-        .data
-        .album_union
-        .tracks
-        .items
+    let album_tracks = items
         .into_iter()
         .filter_map(|v| {
             if v.track.playability.playable {
@@ -87,108 +60,66 @@ pub(crate) async fn fetch_album_tracks(
         })
         .collect::<Vec<String>>();
 
-    // check if there are any more pages to fetch
-    if total_track_count <= constants::ITEMS_PER_PAGE {
-        log!("Playable tracks in {album_id}: {}", album_tracks.len());
-        return album_tracks;
-    }
-
-    // allocate enough space for all tracks since it is known in advance
-    album_tracks.reserve(total_track_count as usize - album_tracks.len());
-
-    // the next page will start where the first one ended
-    variables.offset = variables.limit;
-    url = match build_get_url(
-        constants::operations::ALBUM_TRACKS,
-        &variables,
-        constants::persistent_queries::GET_ALBUM,
-    ) {
-        Ok(v) => v,
-        Err(_) => return Vec::new(),
-    };
-
-    // fetch the rest of the track pages in a loop
-    // this will end on the first error
-    // TODO: make a more reliable loop when retries are available
-    while let Ok(items) =
-        execute_http_request::<models::album::AlbumTracksRoot, Option<IgnoredData>>(
-            auth_header_value,
-            token_header_value,
-            &url,
-            None,
-            runtime,
-        )
-        .await
-    {
-        // check if spotify returned any items at all
-        if items.data.album_union.tracks.items.is_empty() {
-            log!("Spotify returned empty items list");
-            log!("Playable tracks in {album_id}: {}", album_tracks.len());
-            return album_tracks;
-        }
-
-        let mut items = items
-            .data
-            .album_union
-            .tracks
-            .items
-            .into_iter()
-            .filter_map(|v| {
-                if v.track.playability.playable {
-                    Some(v.track.uri.replace(constants::ID_PREFIX_TRACK, ""))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<String>>();
+    log!("Playable tracks in {album_id}: {}", album_tracks.len());
+    Ok(album_tracks)
+}
 
-        // add the list of items to the local collection
-        album_tracks.append(&mut items);
-        // the next page will start where the first one ended
-        variables.offset += variables.limit;
-        url = match build_get_url(
-            constants::operations::ALBUM_TRACKS,
-            &variables,
-            constants::persistent_queries::GET_ALBUM,
-        ) {
-            Ok(v) => v,
-            Err(_) => return Vec::new(),
-        };
+/// A single entry in a playlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PlaylistItem {
+    /// E.g. `b7ba08bda4cbbd8e`
+    pub uid: String,
+    /// Track ID without the `spotify:track:` prefix, None for items that are not tracks
+    pub track_id: Option<String>,
+}
 
-        // protect against very large playlists
-        if variables.offset >= max_number_of_tracks {
-            log!(
-                "Too many tracks ({}), limit {}",
-                variables.offset,
-                max_number_of_tracks
-            );
-            break;
-        }
+/// Who can change a playlist
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PlaylistAccess {
+    /// E.g. `spotify:user:onebro.me`
+    pub owner_uri: String,
+    pub collaborative: bool,
+    /// None if Spotify did not say what the current user can do
+    pub can_edit_items: Option<bool>,
+}
 
-        // for debugging
-        if variables.offset > 20 {
-            break;
+impl PlaylistAccess {
+    fn new(playlist: &PlaylistV2) -> Self {
+        Self {
+            owner_uri: playlist.owner_v2.data.uri.clone(),
+            collaborative: playlist.collaborative,
+            can_edit_items: playlist
+                .current_user_capabilities
+                .as_ref()
+                .map(|v| v.can_edit_items),
         }
     }
 
-    log!("Playable tracks in {album_id}: {}", album_tracks.len());
-    album_tracks
+    /// Returns true if the user can add, remove and move items in the playlist.
+    /// The ownership and the collaborative flag come from the captured samples, so they are checked first.
+    /// The capabilities are not in the samples and are only used for playlists the user neither owns nor collaborates on.
+    pub fn can_edit(&self, user_uri: &str) -> bool {
+        self.owner_uri == user_uri || self.collaborative || self.can_edit_items.unwrap_or_default()
+    }
 }
 
-/// Contains the list of tracks and the owner ID of the playlist
+/// Contains the list of tracks and who can change the playlist
 pub(crate) struct PlaylistTracks {
     pub tracks: Vec<String>,
-    pub owner_uri: String,
+    pub access: PlaylistAccess,
+    /// All the items in the playlist order, including the ones that are not playable tracks
+    pub items: Vec<PlaylistItem>,
 }
 
-/// Returns IDs of all playlist tracks.
-pub(crate) async fn fetch_playlist_tracks(
+/// Returns IDs of all playlist tracks, up to `max_number_of_tracks` or all of them if None.
+pub(crate) async fn fetch_playlist_tracks<T: Transport>(
     auth_header_value: &str,
     token_header_value: &str,
     playlist_id: &str,
-    max_number_of_tracks: usize,
-    runtime: &BrowserRuntime,
-) -> Option<PlaylistTracks> {
+    max_number_of_tracks: Option<usize>,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<PlaylistTracks> {
     log!("fetch_playlist_tracks for: {playlist_id}");
 
     // request examples
@@ -197,63 +128,41 @@ pub(crate) async fn fetch_playlist_tracks(
     // variables: {"uri":"spotify:playlist:60C3LAHXga4hw73HeyU7U0","offset":0,"limit":25}
     // extensions: {"persistedQuery":{"version":1,"sha256Hash":"73a3b3470804983e4d55d83cd6cc99715019228fd999d51429cc69473a18789d"}}
 
-    // get the first page of albums to see how many there are
-    let mut variables = VariablesAlbumOrPlaylistTracks {
+    let variables = VariablesAlbumOrPlaylistTracks {
         uri: [constants::ID_PREFIX_PLAYLIST, playlist_id].concat(),
         ..Default::default()
     };
 
-    let mut url = match build_get_url(
-        constants::operations::PLAYLIST_TRACKS,
-        &variables,
-        constants::persistent_queries::FETCH_PLAYLIST,
-    ) {
-        Ok(v) => v,
-        Err(_) => {
-            return None;
-        }
-    };
-
-    // get the list of album tracks from Spotify
-    let tracks = match execute_http_request::<models::playlist::PlaylistRoot, Option<IgnoredData>>(
+    // get the list of playlist tracks from Spotify
+    let (first_page, items) = fetch_pages::<FetchPlaylist, _>(
         auth_header_value,
         token_header_value,
-        &url,
-        None,
-        runtime,
+        hashes,
+        variables,
+        max_number_of_tracks,
+        transport,
     )
-    .await
-    {
-        Ok(v) => v,
-        Err(e) if matches!(e, RetryAfter::Never) => {
-            return None;
-        }
-        Err(_) => {
-            unimplemented!("Retries are not implemented");
-        }
-    };
-
-    log!("{:?}", tracks.data.playlist_v2.owner_v2);
-
-    // get the owner ID
-    let owner_uri = tracks.data.playlist_v2.owner_v2.data.uri;
+    .await?;
+
+    // get the owner ID and the permissions
+    let access = PlaylistAccess::new(&first_page.data.playlist_v2);
+    log!("{access:?}");
+
+    let playlist_items = items
+        .iter()
+        .map(|v| PlaylistItem {
+            uid: v.uid.clone(),
+            track_id: v
+                .item_v2
+                .data
+                .uri
+                .as_ref()
+                .and_then(|uri| uri.strip_prefix(constants::ID_PREFIX_TRACK))
+                .map(|v| v.to_owned()),
+        })
+        .collect::<Vec<PlaylistItem>>();
 
-    // get the number of lib_v3_items and calculate the number of pages that can be downloaded
-    let total_track_count = tracks.data.playlist_v2.content.total_count;
-    let total_pages = total_track_count / constants::ITEMS_PER_PAGE;
-    let total_pages = if total_track_count % constants::ITEMS_PER_PAGE > 0 {
-        total_track_count / constants::ITEMS_PER_PAGE + 1
-    } else {
-        total_pages
-    };
-    log!("Tracks: {total_track_count}, pages: {total_pages}, owner: {owner_uri}");
-
-    // a collection of all albums from all pages
-    let mut tracks = tracks
-        .data
-        .playlist_v2
-        .content
-        .items
+    let tracks = items
         .into_iter()
         .filter_map(|v| {
             // if the track is playable and is actually a track, then it should be included
@@ -273,110 +182,31 @@ pub(crate) async fn fetch_playlist_tracks(
         })
         .collect::<Vec<String>>();
 
-    // check if there are any more pages to fetch
-    if total_track_count <= constants::ITEMS_PER_PAGE {
-        log!("Playable tracks in {playlist_id}: {}", tracks.len());
-        return Some(PlaylistTracks { tracks, owner_uri });
-    }
-
-    // allocate enough space for all tracks since it is known in advance
-    tracks.reserve(total_track_count as usize - tracks.len());
-
-    // the next page will start where the first one ended
-    variables.offset = variables.limit;
-    url = match build_get_url(
-        constants::operations::PLAYLIST_TRACKS,
-        &variables,
-        constants::persistent_queries::FETCH_PLAYLIST,
-    ) {
-        Ok(v) => v,
-        Err(_) => return None,
-    };
-
-    // fetch the rest of the track pages in a loop
-    // this will end on the first error
-    // TODO: make a more reliable loop when retries are available
-    while let Ok(items) =
-        execute_http_request::<models::playlist::PlaylistRoot, Option<IgnoredData>>(
-            auth_header_value,
-            token_header_value,
-            &url,
-            None,
-            runtime,
-        )
-        .await
-    {
-        // check if spotify returned any items at all
-        if items.data.playlist_v2.content.items.is_empty() {
-            log!("Spotify returned empty items list");
-            log!("Playable tracks in {playlist_id}: {}", tracks.len());
-            return Some(PlaylistTracks { tracks, owner_uri });
-        }
-
-        let mut items = items
-            .data
-            .playlist_v2
-            .content
-            .items
-            .into_iter()
-            .filter_map(|v| {
-                if let Some(uri) = v.item_v2.data.uri {
-                    if v.item_v2.data.playability.playable
-                        && uri.starts_with(constants::ID_PREFIX_TRACK)
-                    {
-                        Some(uri.replace(constants::ID_PREFIX_TRACK, ""))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<String>>();
-
-        // add the list of items to the local collection
-        tracks.append(&mut items);
-        // the next page will start where the first one ended
-        variables.offset += variables.limit;
-        url = match build_get_url(
-            constants::operations::PLAYLIST_TRACKS,
-            &variables,
-            constants::persistent_queries::FETCH_PLAYLIST,
-        ) {
-            Ok(v) => v,
-            Err(_) => return None,
-        };
-
-        // protect against very large playlists
-        if variables.offset >= max_number_of_tracks {
-            log!(
-                "Too many tracks ({}), limit {}",
-                variables.offset,
-                max_number_of_tracks
-            );
-            break;
-        }
-
-        // for debugging
-        if variables.offset > 20 {
-            break;
-        }
-    }
-
-    log!("Playable tracks in {playlist_id}: {}", tracks.len());
+    log!(
+        "Playable tracks in {playlist_id}: {}, owner: {}",
+        tracks.len(),
+        access.owner_uri
+    );
 
-    Some(PlaylistTracks { tracks, owner_uri })
+    Ok(PlaylistTracks {
+        tracks,
+        access,
+        items: playlist_items,
+    })
 }
 
-/// Returns IDs of either albums or playlists.
+/// Returns URIs of either albums or playlists, e.g. `spotify:album:7h5qFidHM4sqhcCHSbiMzL`.
 ///
 /// * filter - either Albums or Playlists, goes into request vars
-pub(crate) async fn fetch_lib_v3_items(
+/// * order - the order Spotify lists them in
+pub(crate) async fn fetch_lib_v3_items<T: Transport>(
     auth_header_value: &str,
     token_header_value: &str,
     filter: &str,
-    runtime: &BrowserRuntime,
-) -> Vec<String> {
+    order: LibraryOrder,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<Vec<String>> {
     log!("fetch_lib_v3_items entered, filter: {filter}");
 
     // request examples
@@ -384,137 +214,85 @@ pub(crate) async fn fetch_lib_v3_items(
     // operationName: libraryV3
     // variables: {"filters":["Playlists"],"order":null,"textFilter":"","features":["LIKED_SONGS","YOUR_EPISODES"],"limit":50,"offset":6,"flatten":false,"expandedFolders":[],"folderUri":null,"includeFoldersWhenFlattening":true,"withCuration":false}
 
-    // get the first page of albums to see how many there are
     let mut variables = VariablesV3Items::default();
     variables.filters.push(filter.to_owned());
-
-    let mut url = match build_get_url(
-        constants::operations::ALBUMS_PLAYLISTS,
-        &variables,
-        constants::persistent_queries::LIBRARY_V3,
-    ) {
-        Ok(v) => v,
-        Err(_) => return Vec::new(),
-    };
+    variables.order = order.as_variable().to_owned();
 
     // the part of the structure we use for our needs are identical between albums and playlists
-    let lib_v3_items = match execute_http_request::<
-        models::albums_playlists::LibV3ItemsRoot,
-        Option<IgnoredData>,
-    >(auth_header_value, token_header_value, &url, None, runtime)
-    .await
-    {
-        Ok(v) => v,
-        Err(e) if matches!(e, RetryAfter::Never) => {
-            return Vec::new();
-        }
-        Err(_) => {
-            unimplemented!("Retries are not implemented");
-        }
-    };
-
-    // log!("{:?}", lib_v3_items);
+    let (_, items) = fetch_pages::<LibraryV3, _>(
+        auth_header_value,
+        token_header_value,
+        hashes,
+        variables,
+        None,
+        transport,
+    )
+    .await?;
 
-    // get the number of lib_v3_items and calculate the number of pages that can be downloaded
-    let total_item_count = lib_v3_items.data.me.library_v3.total_count;
-    let total_pages = total_item_count / constants::ITEMS_PER_PAGE;
-    let total_pages = if total_item_count % constants::ITEMS_PER_PAGE > 0 {
-        total_item_count / constants::ITEMS_PER_PAGE + 1
-    } else {
-        total_pages
-    };
-    log!("Items: {total_item_count}, pages: {total_pages}");
-
-    // a collection of all albums from all pages
-    let mut all_lib_v3_items = lib_v3_items
-        .data
-        .me
-        .library_v3
-        .items
+    let all_lib_v3_items = items
         .into_iter()
         .map(|v| v.item.data.uri)
         .collect::<Vec<String>>();
 
-    // check if there are any more pages to fetch
-    if total_item_count <= constants::ITEMS_PER_PAGE {
-        log!("Total {filter}: {}", all_lib_v3_items.len());
-        return all_lib_v3_items;
-    }
-
-    // allocate enough space for all albums since it is known in advance
-    all_lib_v3_items.reserve(total_item_count as usize - all_lib_v3_items.len());
-
-    // the next page will start where the first one ended
-    variables.offset = variables.limit;
-    url = match build_get_url(
-        constants::operations::ALBUMS_PLAYLISTS,
-        &variables,
-        constants::persistent_queries::LIBRARY_V3,
-    ) {
-        Ok(v) => v,
-        Err(_) => return Vec::new(),
-    };
+    log!("Total {filter}: {}", all_lib_v3_items.len());
+    Ok(all_lib_v3_items)
+}
 
-    // fetch the rest of the item pages in a loop
-    // this will end on the first error
-    // TODO: make a more reliable loop when retries are available
-    while let Ok(items) = execute_http_request::<
-        models::albums_playlists::LibV3ItemsRoot,
-        Option<IgnoredData>,
-    >(auth_header_value, token_header_value, &url, None, runtime)
-    .await
-    {
-        // check if spotify returned any items at all
-        if items.data.me.library_v3.items.is_empty() {
-            log!("Spotify returned empty items list");
-            log!("Total {filter}: {}", all_lib_v3_items.len());
-            return all_lib_v3_items;
-        }
+/// Returns IDs of all playable tracks from Liked Songs.
+pub(crate) async fn fetch_liked_tracks<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<Vec<String>> {
+    log!("fetch_liked_tracks entered");
 
-        let mut items = items
-            .data
-            .me
-            .library_v3
-            .items
-            .into_iter()
-            .map(|v| v.item.data.uri.replace(constants::ID_PREFIX_ALBUM, ""))
-            .collect::<Vec<String>>();
+    // request examples
+    // https://api-partner.spotify.com/pathfinder/v1/query?operationName=fetchLibraryTracks&variables=%7B%22offset%22%3A0%2C%22limit%22%3A50%7D&extensions=%7B%22persistedQuery%22%3A%7B%22version%22%3A1%2C%22sha256Hash%22%3A%228474ec383b530ce3e54611fca2d8e3da57ef5612877838b8dbf00bd9fc692dfb%22%7D%7D
+    // operationName: fetchLibraryTracks
+    // variables: {"offset":0,"limit":50}
+    // extensions: {"persistedQuery":{"version":1,"sha256Hash":"8474ec383b530ce3e54611fca2d8e3da57ef5612877838b8dbf00bd9fc692dfb"}}
 
-        // add the list of items to the local collection
-        all_lib_v3_items.append(&mut items);
-        // the next page will start where the first one ended
-        variables.offset += variables.limit;
-        url = match build_get_url(
-            constants::operations::ALBUMS_PLAYLISTS,
-            &variables,
-            constants::persistent_queries::LIBRARY_V3,
-        ) {
-            Ok(v) => v,
-            Err(_) => return Vec::new(),
-        };
+    let (_, items) = fetch_pages::<FetchLibraryTracks, _>(
+        auth_header_value,
+        token_header_value,
+        hashes,
+        VariablesLikedTracks::default(),
+        None,
+        transport,
+    )
+    .await?;
 
-        // for debugging
-        // if variables.offset > 50 {
-        //     break;
-        // }
-    }
+    let all_liked_tracks = items
+        .into_iter()
+        .filter_map(|v| {
+            if v.track.data.playability.playable {
+                Some(v.track.uri.replace(constants::ID_PREFIX_TRACK, ""))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<String>>();
 
-    log!("Total {filter}: {}", all_lib_v3_items.len());
-    all_lib_v3_items
+    log!("Playable liked tracks: {}", all_liked_tracks.len());
+    Ok(all_liked_tracks)
 }
 
-/// Adds the specified list of tracks to the playlist and reports on its progress.
-/// Failed additions are logged, but do not stop processing. Does not panic.  
+/// Adds the specified list of tracks to the playlist at the position in lots of `constants::ADD_ITEMS_PER_REQUEST`.
+/// Failed additions are logged, but do not stop processing unless the error is fatal. Does not panic.
+/// Stops without an error if the job was cancelled.
 /// Returns the number of tracks added.
-pub(crate) async fn add_tracks_to_playlist(
+pub(crate) async fn add_tracks_to_playlist<T: Transport>(
     auth_header_value: &str,
     token_header_value: &str,
     playlist_id: &str,
     tracks_to_add: Vec<String>,
-    runtime: &BrowserRuntime,
-) -> usize {
+    position: &InsertPosition,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<usize> {
     log!(
-        "add_tracks_to_playlist for: {playlist_id}, tracks: {}",
+        "add_tracks_to_playlist for: {playlist_id}, tracks: {}, position: {position:?}",
         tracks_to_add.len()
     );
 
@@ -528,65 +306,237 @@ pub(crate) async fn add_tracks_to_playlist(
 
     // only the list of tracks will change per request
     // unlike with GET requests, we need to prepare the entire payload as a struct
-    let mut payload = Payload::<VariablesAddTracksToPlaylist> {
-        variables: VariablesAddTracksToPlaylist {
+    let mut payload = Payload::new::<AddToPlaylist>(
+        VariablesAddTracksToPlaylist {
             playlist_uri: [constants::ID_PREFIX_PLAYLIST, playlist_id].concat(),
+            new_position: NewPosition::from(position),
             ..Default::default()
         },
-        operation_name: constants::operations::ADD_TO_PLAYLIST.to_owned(),
-        extensions: PayloadExtensions {
-            persisted_query: PersistedQuery {
-                sha256_hash: constants::persistent_queries::ADD_TO_PLAYLIST.to_owned(),
-                ..Default::default()
-            },
-        },
-    };
+        hashes,
+    );
 
-    // add tracks in lots of 50s
-    // TODO: this loop is completely faulty and has to be redesigned
-    // it repeats the same tracks over and over
-    for (idx, track) in tracks_to_add.iter().enumerate() {
-        // add the track to the list
-        payload
-            .variables
-            .uris
-            .push([constants::ID_PREFIX_TRACK, track].concat());
-
-        // send the request to spotify for what is in the payload now 
-        // if the list is longer than a certain size or if it's the last pass
-        // Spotify seems to be OK with 500 tracks at a time
-        if idx % 99 == 0 || idx == tracks_to_add.len() - 1 {
-            // ignore the response payload for now
-            match execute_http_request::<models::IgnoredData, _>(
-                auth_header_value,
-                token_header_value,
-                BUILD_POST_URL,
-                Some(&payload),
-                runtime,
-            )
-            .await
-            {
-                Ok(_) => {
-                    log!("Added {} tracks", idx + 1);
-                    tracks_added += payload.variables.uris.len();
-                    // clear for the next lot of tracks
-                    payload.variables.uris.clear();
-                }
-                Err(e) if matches!(e, RetryAfter::Never) => {
-                    log!("Failed to add {} tracks", idx + 1);
-                    tracks_missed += payload.variables.uris.len();
-                    // clear the list to make room for the next lot of tracks
-                    // it may build up until the request is too large if we don't clear it
-                    payload.variables.uris.clear();
-                    continue;
-                }
-                Err(_) => {
-                    unimplemented!("Retries are not implemented");
-                }
-            };
-        }
+    // every lot goes to the same position, so the last lot goes first unless it is the bottom of the playlist
+    let mut lots = tracks_to_add
+        .chunks(constants::ADD_ITEMS_PER_REQUEST)
+        .collect::<Vec<&[String]>>();
+    if position.reverses_lots() {
+        lots.reverse();
+    }
+
+    for lot in lots {
+        payload.variables.uris = lot
+            .iter()
+            .map(|v| [constants::ID_PREFIX_TRACK, v].concat())
+            .collect();
+
+        // ignore the response payload for now
+        match execute_with_retry::<AddToPlaylist, _>(
+            auth_header_value,
+            token_header_value,
+            BUILD_POST_URL,
+            Some(&payload),
+            transport,
+        )
+        .await
+        {
+            Ok(_) => {
+                tracks_added += lot.len();
+                log!("Added {tracks_added} tracks");
+            }
+            // the tracks added so far stay in the playlist and are reported by the caller
+            Err(Error::Cancelled { .. }) => {
+                log!("Cancelled after adding {tracks_added} tracks");
+                break;
+            }
+            // there is no point sending more requests if e.g. the token expired
+            Err(e) if e.is_fatal() => {
+                log!("Failed to add {} tracks, giving up", lot.len());
+                return Err(e);
+            }
+            Err(_) => {
+                log!("Failed to add {} tracks", lot.len());
+                tracks_missed += lot.len();
+            }
+        };
     }
     log!("All tracks added: {tracks_added}, missed: {tracks_missed}");
 
-    tracks_added
+    Ok(tracks_added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::CannedTransport;
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    const ALBUM_ID: &str = "7h5qFidHM4sqhcCHSbiMzL";
+
+    #[test]
+    fn retry_on_rate_limit_and_server_errors() {
+        let transport = CannedTransport::default()
+            .respond_with(
+                constants::operations::ALBUM_TRACKS,
+                &[],
+                429,
+                &[("Retry-After", "7")],
+                "",
+            )
+            .times(1)
+            .respond_with(constants::operations::ALBUM_TRACKS, &[], 503, &[], "")
+            .times(1)
+            .respond(
+                constants::operations::ALBUM_TRACKS,
+                include_str!("../samples/getAlbum-resp.json"),
+            );
+
+        let tracks = block_on(fetch_album_tracks(
+            "a",
+            "t",
+            ALBUM_ID,
+            None,
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        assert_eq!(tracks.len(), 12);
+        let sleeps = transport.sleeps.borrow();
+        assert_eq!(sleeps.len(), 2);
+        assert!(sleeps[0] >= Duration::from_secs(7));
+        assert!(sleeps[1] >= Duration::from_millis(constants::RETRY_BASE_DELAY_MS * 2));
+    }
+
+    #[test]
+    fn give_up_after_max_attempts() {
+        let transport = CannedTransport::default().respond_with(
+            constants::operations::ALBUM_TRACKS,
+            &[],
+            502,
+            &[],
+            "",
+        );
+
+        let result = block_on(fetch_album_tracks(
+            "a",
+            "t",
+            ALBUM_ID,
+            None,
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::Http { status: 502, .. })
+        ));
+        assert_eq!(
+            transport.requests.borrow().len(),
+            constants::MAX_RETRY_ATTEMPTS as usize
+        );
+    }
+
+    #[test]
+    fn retry_adding_tracks_on_rate_limit_only() {
+        // the lot may have been added before the server error, so it is not sent again
+        let transport = CannedTransport::default()
+            .respond_with(constants::operations::ADD_TO_PLAYLIST, &[], 502, &[], "")
+            .times(1)
+            .respond_with(
+                constants::operations::ADD_TO_PLAYLIST,
+                &[],
+                429,
+                &[("Retry-After", "1")],
+                "",
+            )
+            .times(1)
+            .respond(
+                constants::operations::ADD_TO_PLAYLIST,
+                include_str!("../samples/add-track.json"),
+            );
+        let tracks = (0..constants::ADD_ITEMS_PER_REQUEST + 1)
+            .map(|v| format!("track{v}"))
+            .collect::<Vec<String>>();
+
+        let added = block_on(add_tracks_to_playlist(
+            "a",
+            "t",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            tracks,
+            &InsertPosition::Bottom,
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        // the first lot failed with 502, the second one went through after 429
+        assert_eq!(added, 1);
+        assert_eq!(
+            transport
+                .requests_for(constants::operations::ADD_TO_PLAYLIST)
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn keep_track_order_when_adding_to_top() {
+        let transport = CannedTransport::default().respond(
+            constants::operations::ADD_TO_PLAYLIST,
+            include_str!("../samples/add-track.json"),
+        );
+        let tracks = (0..constants::ADD_ITEMS_PER_REQUEST + 1)
+            .map(|v| format!("track{v}"))
+            .collect::<Vec<String>>();
+
+        let added = block_on(add_tracks_to_playlist(
+            "a",
+            "t",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            tracks,
+            &InsertPosition::Top,
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        // the last lot goes to the top first and the first lot goes on top of it
+        assert_eq!(added, constants::ADD_ITEMS_PER_REQUEST + 1);
+        let requests = transport.requests_for(constants::operations::ADD_TO_PLAYLIST);
+        let last_track = format!("track{}", constants::ADD_ITEMS_PER_REQUEST);
+        assert!(requests[0].variables.contains(&last_track));
+        assert!(requests[1].variables.contains(r#""spotify:track:track0""#));
+    }
+
+    #[test]
+    fn edit_own_and_collaborative_playlists() {
+        let own = PlaylistAccess {
+            owner_uri: "spotify:user:onebro.me".to_owned(),
+            ..Default::default()
+        };
+        assert!(own.can_edit("spotify:user:onebro.me"));
+        assert!(!own.can_edit("spotify:user:someone-else"));
+
+        let collaborative = PlaylistAccess {
+            collaborative: true,
+            ..own.clone()
+        };
+        assert!(collaborative.can_edit("spotify:user:someone-else"));
+
+        // the capabilities are not trusted over the ownership and the collaborative flag
+        let read_only = PlaylistAccess {
+            can_edit_items: Some(false),
+            ..collaborative
+        };
+        assert!(read_only.can_edit("spotify:user:someone-else"));
+        assert!(read_only.can_edit("spotify:user:onebro.me"));
+
+        // but they are the only hint for playlists owned by others that are not collaborative
+        let editable = PlaylistAccess {
+            can_edit_items: Some(true),
+            ..own
+        };
+        assert!(editable.can_edit("spotify:user:someone-else"));
+    }
 }
@@ -1,187 +1,400 @@
-use std::fmt::Debug;
+use std::time::Duration;
 
-// use std::time::Duration;
-use crate::{constants::log, models::Payload, BrowserRuntime, Result, RetryAfter};
-use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::JsFuture;
-use web_sys::{Request, RequestInit, RequestMode, Response};
+use crate::{
+    cancel, constants,
+    constants::log,
+    hash_registry::HashRegistry,
+    models::{
+        graphql::GraphQlErrorsRoot,
+        operations::Operation,
+        pagination::{Page, PaginatedVariables},
+        Payload,
+    },
+    transport::{HttpRequest, Transport},
+    Error, Result,
+};
+use rand::Rng;
 
 /// Prepares and executes an HTTP request to spotify.
 /// ## Types
-/// * R - Response, always required
-/// * P - Payload, may be omitted
+/// * O - the operation with its variables and response types
+/// * T - the transport that sends the request
 /// ## Request types
 /// * GET - if no payload is provided
 /// * POST - if payload is provided
+///
 /// The requests includes token and other headers.
-pub(super) async fn execute_http_request<R, P>(
+pub(super) async fn execute_http_request<O, T>(
     auth_header_value: &str,
     token_header_value: &str,
     url: &str,
-    payload: Option<&Payload<P>>,
-    runtime: &BrowserRuntime,
-) -> Result<R>
+    payload: Option<&Payload<O::Variables>>,
+    transport: &T,
+) -> Result<O::Response>
 where
-    R: for<'de> serde::Deserialize<'de>,
-    P: serde::Serialize,
+    O: Operation,
+    T: Transport,
 {
+    let operation = O::NAME;
+
     // log!("execute_get_request entered");
+    // these keys were manually extracted from chrome webdev tools
+    let mut headers = vec![
+        ("Accept", "application/json"),
+        ("authorization", auth_header_value),
+        ("client-token", token_header_value),
+    ];
+
     // set request params
-    let mut opts = RequestInit::new();
-    opts.mode(RequestMode::Cors);
-    match payload {
-        Some(v) => {
-            opts.method("POST");
-
-            match serde_json::to_string(v) {
-                Ok(v) => {
-                    opts.body(Some(&wasm_bindgen::JsValue::from_str(&v)));
-                }
-                Err(e) => {
-                    log!("Failed to serialize POST payload");
-                    log!("{:?}", e);
-                    // TODO: may be worth a retry
-                    return Err(RetryAfter::Never);
-                }
+    let (method, body) = match payload {
+        Some(v) => match serde_json::to_string(v) {
+            Ok(v) => {
+                // only set the content type if there is POST payload
+                headers.push(("content-type", "application/json"));
+                ("POST", Some(v))
             }
-        }
-        None => {
-            opts.method("GET");
-        }
-    }
+            Err(e) => {
+                log!("Failed to serialize POST payload");
+                log!("{:?}", e);
+                return Err(Error::Schema {
+                    operation,
+                    url: url.to_owned(),
+                    detail: e.to_string(),
+                });
+            }
+        },
+        None => ("GET", None),
+    };
 
     // log!("{url}");
 
-    // create the request
-    let request = match Request::new_with_str_and_init(url, &opts) {
+    check_cancelled(operation, url)?;
+    let resp = match transport
+        .fetch(HttpRequest {
+            method,
+            url,
+            headers,
+            body,
+        })
+        .await
+    {
         Ok(v) => v,
-        Err(e) => {
-            log!("Spotify request creation failed");
-            log!("{:?}", e);
-            // TODO: may be worth a retry
-            return Err(RetryAfter::Never);
+        Err(detail) => {
+            return Err(Error::Network {
+                operation,
+                url: url.to_owned(),
+                detail,
+            })
         }
     };
 
-    // log!("Request created");
+    // log!("HTTP request completed");
 
-    // add headers
-    let _ = request.headers().set("Accept", "application/json");
-    // these keys were manually extracted from chrome webdev tools
-    let _ = request.headers().set("authorization", auth_header_value);
-    let _ = request.headers().set("client-token", token_header_value);
-    if payload.is_some() {
-        // only set the content type if there is POST payload
-        let _ = request.headers().set("content-type", "application/json");
+    match resp.status {
+        200..=299 => {}
+        // the session token has expired or was rejected - no point retrying or continuing
+        401 | 403 => {
+            log!(
+                "Spotify rejected the session token with HTTP {}",
+                resp.status
+            );
+            log!("{url}");
+            return Err(Error::Auth {
+                operation,
+                url: url.to_owned(),
+                status: resp.status,
+            });
+        }
+        // Spotify asks clients to back off with 429 for the number of seconds from Retry-After header, if any
+        429 => {
+            let retry_after = resp
+                .headers
+                .get("retry-after")
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .unwrap_or_default();
+            log!("Spotify responded with HTTP 429, retry after {retry_after}s");
+            log!("{url}");
+            return Err(Error::RateLimited {
+                operation,
+                url: url.to_owned(),
+                retry_after: Duration::from_secs(retry_after),
+            });
+        }
+        // the album or playlist is gone or is not available to this user (404),
+        // or Spotify is temporarily unavailable (5xx)
+        404 | 500..=599 => {
+            log!("Spotify responded with HTTP {}", resp.status);
+            log!("{url}");
+            return Err(Error::Http {
+                operation,
+                url: url.to_owned(),
+                status: resp.status,
+            });
+        }
+        // GraphQL errors may come with 400 and other statuses
+        // they are checked for below
+        _ => {
+            log!("Spotify responded with HTTP {}", resp.status);
+            log!("{url}");
+        }
     }
 
-    // log!("Headers set");
+    // GraphQL reports errors with data=null and the list of errors
+    // this has to be checked first because some response types, e.g. IgnoredData, would accept anything
+    if let Ok(graphql) = serde_json::from_str::<GraphQlErrorsRoot>(&resp.body) {
+        if graphql.is_error() {
+            let messages = graphql
+                .errors
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            log!("Spotify returned GraphQL errors: {messages}");
+            log!("{url}");
 
-    // both window and globalscope have the same interface, but they are separate types so Rust has
-    // to have separate paths for them  
-    // the output is the same type for both
-    let resp_value = match runtime {
-        BrowserRuntime::ChromeWorker(v) => JsFuture::from(v.fetch_with_request(&request)).await,
-        BrowserRuntime::FireFoxWindow(v) => JsFuture::from(v.fetch_with_request(&request)).await,
-    };
+            if graphql
+                .errors
+                .iter()
+                .any(|v| v.is_persisted_query_not_found())
+            {
+                return Err(Error::PersistedQueryNotFound {
+                    operation,
+                    url: url.to_owned(),
+                });
+            }
 
-    // unwrap the response
-    let resp_value = match resp_value {
-        Ok(v) => v,
-        Err(e) => {
-            log!("Spotify request failed");
-            log!("{url}");
-            log!("{:?}", e);
-            // TODO: may be worth a retry
-            return Err(RetryAfter::Never);
+            return Err(Error::GraphQl {
+                operation,
+                url: url.to_owned(),
+                messages,
+            });
         }
-    };
+    }
 
-    // exit if the response is not of the expected type
-    if !resp_value.is_instance_of::<Response>() {
-        log!("Spotify response in not Response");
-        log!("{url}");
-        log!("{:?}", resp_value);
-        // TODO: may be worth a retry
-        return Err(RetryAfter::Never);
-    };
+    // log!("Resp as string:");
+    // log!("{}", resp.body);
 
-    // this is unlikely to fail because of the previous type check
-    let resp: Response = match resp_value.dyn_into() {
-        Ok(v) => v,
-        Err(e) => {
-            log!("Cannot typecast response to Response");
+    // convert into a rust struct
+    let playlist = match serde_json::from_str::<O::Response>(&resp.body) {
+        Ok(v) if (200..300).contains(&resp.status) => v,
+        Ok(_) => {
+            log!("Unexpected HTTP {} for a valid response", resp.status);
             log!("{url}");
-            log!("{:?}", e);
-            // TODO: may be worth a retry
-            return Err(RetryAfter::Never);
+            return Err(Error::Http {
+                operation,
+                url: url.to_owned(),
+                status: resp.status,
+            });
         }
-    };
-
-    // Read the response stream to completion.
-    // In theory, the stream may still be open and the op may take some time to complete
-    let resp = match resp.json() {
-        Ok(v) => JsFuture::from(v).await,
         Err(e) => {
-            log!("Cannot convert Promise to Future");
+            log!("Cannot deser spotify response into rust struct");
             log!("{url}");
             log!("{:?}", e);
-            // TODO: may be worth a retry
-            return Err(RetryAfter::Never);
+            return Err(Error::Schema {
+                operation,
+                url: url.to_owned(),
+                detail: e.to_string(),
+            });
         }
     };
 
-    // log!("HTTP request completed");
+    Ok(playlist)
+}
 
-    // Unwrap the response and handle the error
-    let resp = match resp {
-        Ok(v) => v,
-        Err(e) => {
-            log!("Spotify request failed");
+/// Returns an error instead of sending the request if the user cancelled the job.
+fn check_cancelled(operation: &'static str, url: &str) -> Result<()> {
+    if !cancel::is_cancelled() {
+        return Ok(());
+    }
+
+    log!("Cancelled before {operation}");
+    Err(Error::Cancelled {
+        operation,
+        url: url.to_owned(),
+    })
+}
+
+/// Executes the request via `execute_http_request` and retries it if the error has `retry_after`,
+/// waiting for longer after every attempt. POST requests are retried on 429 only.
+/// Gives up with the last error after `constants::MAX_RETRY_ATTEMPTS` attempts
+/// or if Spotify asks to wait for longer than `constants::MAX_RETRY_DELAY_MS`.
+pub(super) async fn execute_with_retry<O, T>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    url: &str,
+    payload: Option<&Payload<O::Variables>>,
+    transport: &T,
+) -> Result<O::Response>
+where
+    O: Operation,
+    T: Transport,
+{
+    let mut attempt: u32 = 1;
+
+    loop {
+        let error = match execute_http_request::<O, T>(
+            auth_header_value,
+            token_header_value,
+            url,
+            payload,
+            transport,
+        )
+        .await
+        {
+            Err(e) => e,
+            result => return result,
+        };
+
+        // a POST changes the playlist and a 5xx may come back after the change was made,
+        // so only 429 means it is safe to send it again
+        let retry_after = match error.retry_after() {
+            Some(v) if payload.is_none() || matches!(error, Error::RateLimited { .. }) => v,
+            _ => return Err(error),
+        };
+
+        if attempt >= constants::MAX_RETRY_ATTEMPTS {
+            log!("Giving up after {attempt} attempts");
             log!("{url}");
-            log!("{:?}", e);
-            // TODO: may be worth a retry
-            return Err(RetryAfter::Never);
+            return Err(error);
         }
+
+        let jitter = rand::thread_rng().gen_range(0..=constants::RETRY_BASE_DELAY_MS);
+        let delay = match backoff_delay(attempt, retry_after, jitter) {
+            Some(v) => v,
+            None => {
+                log!(
+                    "Spotify asked to retry in {}s, which is too long to wait",
+                    retry_after.as_secs()
+                );
+                log!("{url}");
+                return Err(error);
+            }
+        };
+
+        log!(
+            "Attempt {attempt} failed, retrying in {}ms",
+            delay.as_millis()
+        );
+        transport.sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Returns how long to wait before the next attempt: the longer of the exponential backoff
+/// with jitter and what Spotify asked for in Retry-After.
+/// Returns None if Spotify asked to wait for longer than `constants::MAX_RETRY_DELAY_MS`.
+/// * attempt - the number of the attempt that just failed, starting from 1
+/// * retry_after - the value of Retry-After header, 0 if there was none
+/// * jitter - a random number of milliseconds to add to the backoff
+pub(super) fn backoff_delay(attempt: u32, retry_after: Duration, jitter: u64) -> Option<Duration> {
+    if retry_after > Duration::from_millis(constants::MAX_RETRY_DELAY_MS) {
+        return None;
+    }
+
+    let backoff = constants::RETRY_BASE_DELAY_MS
+        .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1)))
+        .min(constants::MAX_RETRY_DELAY_MS)
+        + jitter;
+
+    Some(Duration::from_millis(backoff).max(retry_after))
+}
+
+/// Fetches a paginated list page by page, starting from the offset in `variables`,
+/// until there are `max_items` items or the end of the list, whichever comes first.
+/// All items are fetched if `max_items` is None.
+/// Returns the first page with the items moved out of it and the items from all the pages.
+/// The first page is useful for the details of the list itself, e.g. the playlist owner.
+pub(super) async fn fetch_pages<O, T>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    hashes: &HashRegistry,
+    mut variables: O::Variables,
+    max_items: Option<usize>,
+    transport: &T,
+) -> Result<(O::Response, Vec<<O::Response as Page>::Item>)>
+where
+    O: Operation,
+    O::Variables: PaginatedVariables,
+    O::Response: Page,
+    T: Transport,
+{
+    let operation = O::NAME;
+    let url = build_get_url::<O>(&variables, hashes)?;
+    let mut first_page =
+        execute_with_retry::<O, T>(auth_header_value, token_header_value, &url, None, transport)
+            .await?;
+    let mut items = first_page.take_items();
+
+    // work out where to stop from the total on the first page
+    let total_count = first_page.total_count();
+    let end = match max_items {
+        Some(v) => total_count.min(variables.offset().saturating_add(v)),
+        None => total_count,
     };
+    let page_size = variables.limit().max(1);
+    log!(
+        "{operation}: total {total_count}, fetching up to {}",
+        end.saturating_sub(variables.offset())
+    );
 
-    // log!("Resp as string:");
-    // log!("{:?}", resp.as_string().unwrap());
+    // allocate enough space for all items since it is known in advance
+    items.reserve(end.saturating_sub(variables.offset() + items.len()));
 
-    // convert into a rust struct
-    let playlist = match serde_wasm_bindgen::from_value::<R>(resp) {
-        Ok(v) => v,
-        Err(e) => {
-            log!("Cannot deser spotify response into rust struct");
-            log!("{url}");
-            log!("{:?}", e);
-            return Err(RetryAfter::Never);
+    // the next page will start where the previous one ended
+    variables.set_offset(variables.offset() + page_size);
+    while variables.offset() < end {
+        let url = build_get_url::<O>(&variables, hashes)?;
+        let mut page = execute_with_retry::<O, T>(
+            auth_header_value,
+            token_header_value,
+            &url,
+            None,
+            transport,
+        )
+        .await?;
+
+        // the list may have become shorter since the first page was fetched
+        let mut page_items = page.take_items();
+        if page_items.is_empty() {
+            log!("Spotify returned empty items list");
+            break;
         }
-    };
+        items.append(&mut page_items);
 
-    Ok(playlist)
+        variables.set_offset(variables.offset() + page_size);
+    }
+
+    // the last page may have more than needed
+    if let Some(max_items) = max_items {
+        items.truncate(max_items);
+    }
+
+    Ok((first_page, items))
 }
 
-/// Returns `https://api-partner.spotify.com/pathfinder/v1/query`.
-/// This could be a constant, but an fn more compatible with `build_get_url`.
+/// The pathfinder endpoint all POST requests go to. The operation is in the payload.
+/// GET requests go to the same endpoint with the operation in the query string, see `build_get_url`.
 pub(super) const BUILD_POST_URL: &str = "https://api-partner.spotify.com/pathfinder/v1/query";
 
 /// Creates a URL with a query string for a GET request to Spotify.
 /// Concatenates the op name, vars and other params into a single string.
-pub(super) fn build_get_url<T>(
-    operation_name: &str,
-    variables: &T,
-    persisted_query_hash: &str,
-) -> Result<String>
-where
-    T: ?Sized + serde::Serialize + Debug,
-{
+pub(super) fn build_get_url<O: Operation>(
+    variables: &O::Variables,
+    hashes: &HashRegistry,
+) -> Result<String> {
+    let operation_name = O::NAME;
+    let persisted_query_hash = hashes.hash::<O>();
+
     let variables = match serde_json::to_string(variables) {
         Ok(v) => v,
         Err(e) => {
             log!("Failed to serialize variables for {:?}", variables);
             log!("{e}");
-            return Err(RetryAfter::Never);
+            return Err(Error::Schema {
+                operation: operation_name,
+                url: BUILD_POST_URL.to_owned(),
+                detail: e.to_string(),
+            });
         }
     };
 
@@ -209,3 +422,250 @@ where
 
     Ok(url.to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            operations::{FetchLibraryTracks, GetAlbum},
+            IgnoredData,
+        },
+        transport::CannedTransport,
+    };
+    use futures::executor::block_on;
+
+    const URL: &str = "https://api-partner.spotify.com/pathfinder/v1/query?operationName=getAlbum";
+
+    /// An operation that accepts any response
+    struct AnyAlbum;
+
+    impl Operation for AnyAlbum {
+        const NAME: &'static str = "getAlbum";
+        const HASH: &'static str = "";
+        type Variables = IgnoredData;
+        type Response = IgnoredData;
+    }
+
+    fn execute(transport: &CannedTransport) -> Result<IgnoredData> {
+        block_on(execute_with_retry::<AnyAlbum, _>(
+            "a", "t", URL, None, transport,
+        ))
+    }
+
+    #[test]
+    fn auth_errors_are_not_retried() {
+        let transport = CannedTransport::default().respond_with("getAlbum", &[], 401, &[], "");
+
+        let error = execute(&transport).unwrap_err();
+        assert!(matches!(error, Error::Auth { status: 401, .. }));
+        assert!(error.is_fatal());
+        assert_eq!(error.operation(), "getAlbum");
+        assert_eq!(error.url(), URL);
+        assert_eq!(transport.requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn do_not_send_requests_once_cancelled() {
+        let transport = CannedTransport::default().respond("getAlbum", "{}");
+
+        cancel::cancel();
+        let result = execute(&transport);
+        cancel::reset();
+
+        assert!(matches!(result, Err(Error::Cancelled { .. })), "{result:?}");
+        assert!(transport.requests.borrow().is_empty());
+    }
+
+    #[test]
+    fn graphql_errors_fail_even_with_http_200() {
+        let transport = CannedTransport::default()
+            .respond(
+                "getAlbum",
+                r#"{"errors":[{"message":"Invalid uri"}],"data":null}"#,
+            )
+            .times(1)
+            .respond(
+                "getAlbum",
+                r#"{"errors":[{"message":"PersistedQueryNotFound","extensions":{"code":"PERSISTED_QUERY_NOT_FOUND"}}],"data":null}"#,
+            );
+
+        assert!(matches!(
+            execute(&transport),
+            Err(Error::GraphQl { messages, .. }) if messages == "Invalid uri"
+        ));
+        assert!(matches!(
+            execute(&transport),
+            Err(Error::PersistedQueryNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_response_is_a_schema_error() {
+        let transport = CannedTransport::default().respond("getAlbum", "<html>");
+
+        assert!(matches!(
+            block_on(execute_with_retry::<GetAlbum, _>(
+                "a", "t", URL, None, &transport
+            )),
+            Err(Error::Schema {
+                operation: "getAlbum",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn partial_data_with_errors_is_ok() {
+        let transport = CannedTransport::default().respond(
+            "getAlbum",
+            r#"{"errors":[{"message":"Something minor"}],"data":{}}"#,
+        );
+
+        assert!(execute(&transport).is_ok());
+    }
+
+    #[test]
+    fn backoff_doubles_until_capped() {
+        assert_eq!(
+            backoff_delay(1, Duration::ZERO, 0),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            backoff_delay(2, Duration::ZERO, 0),
+            Some(Duration::from_millis(1000))
+        );
+        assert_eq!(
+            backoff_delay(3, Duration::ZERO, 7),
+            Some(Duration::from_millis(2007))
+        );
+        assert_eq!(
+            backoff_delay(30, Duration::ZERO, 0),
+            Some(Duration::from_millis(constants::MAX_RETRY_DELAY_MS))
+        );
+    }
+
+    #[test]
+    fn backoff_honours_retry_after() {
+        assert_eq!(
+            backoff_delay(1, Duration::from_secs(10), 0),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(backoff_delay(1, Duration::from_secs(3600), 0), None);
+    }
+
+    /// Returns a page of Liked Songs with `count` tracks starting from `offset`.
+    fn liked_tracks_page(offset: usize, count: usize, total_count: usize) -> String {
+        let items = (offset..offset + count)
+            .map(|v| {
+                format!(
+                    r#"{{"track":{{"_uri":"spotify:track:{v}","data":{{"playability":{{"playable":true}}}}}}}}"#
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            r#"{{"data":{{"me":{{"library":{{"tracks":{{"items":[{items}],"totalCount":{total_count}}}}}}}}}}}"#
+        )
+    }
+
+    fn fetch_liked_tracks_pages(
+        transport: &CannedTransport,
+        max_items: Option<usize>,
+    ) -> Result<Vec<String>> {
+        let (_, items) = block_on(fetch_pages::<FetchLibraryTracks, _>(
+            "a",
+            "t",
+            &HashRegistry::default(),
+            crate::models::VariablesLikedTracks::default(),
+            max_items,
+            transport,
+        ))?;
+        Ok(items.into_iter().map(|v| v.track.uri).collect())
+    }
+
+    /// 120 liked tracks in pages of 50
+    fn liked_tracks_120() -> CannedTransport {
+        CannedTransport::default()
+            .respond_when(
+                constants::operations::LIKED_TRACKS,
+                &[r#""offset":0"#],
+                &liked_tracks_page(0, 50, 120),
+            )
+            .respond_when(
+                constants::operations::LIKED_TRACKS,
+                &[r#""offset":50"#],
+                &liked_tracks_page(50, 50, 120),
+            )
+            .respond_when(
+                constants::operations::LIKED_TRACKS,
+                &[r#""offset":100"#],
+                &liked_tracks_page(100, 20, 120),
+            )
+    }
+
+    #[test]
+    fn fetch_all_pages() {
+        let transport = liked_tracks_120();
+
+        let items = fetch_liked_tracks_pages(&transport, None).unwrap();
+
+        assert_eq!(items.len(), 120);
+        assert_eq!(items[119], "spotify:track:119");
+        assert_eq!(transport.requests.borrow().len(), 3);
+    }
+
+    #[test]
+    fn fetch_only_pages_up_to_max_items() {
+        let transport = liked_tracks_120();
+
+        let items = fetch_liked_tracks_pages(&transport, Some(60)).unwrap();
+
+        assert_eq!(items.len(), 60);
+        assert_eq!(transport.requests.borrow().len(), 2);
+
+        let transport = liked_tracks_120();
+        let items = fetch_liked_tracks_pages(&transport, Some(50)).unwrap();
+
+        assert_eq!(items.len(), 50);
+        assert_eq!(transport.requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn stop_on_empty_page() {
+        let transport = CannedTransport::default()
+            .respond_when(
+                constants::operations::LIKED_TRACKS,
+                &[r#""offset":0"#],
+                &liked_tracks_page(0, 50, 500),
+            )
+            .respond(
+                constants::operations::LIKED_TRACKS,
+                &liked_tracks_page(0, 0, 500),
+            );
+
+        let items = fetch_liked_tracks_pages(&transport, None).unwrap();
+
+        assert_eq!(items.len(), 50);
+        assert_eq!(transport.requests.borrow().len(), 2);
+    }
+
+    #[test]
+    fn url_and_payload_come_from_the_operation() {
+        let url =
+            build_get_url::<FetchLibraryTracks>(&Default::default(), &HashRegistry::default())
+                .unwrap();
+        assert!(url.contains("operationName=fetchLibraryTracks&"));
+        assert!(url.contains(constants::persistent_queries::FETCH_LIBRARY_TRACKS));
+
+        let payload = Payload::new::<crate::models::operations::AddToPlaylist>(
+            Default::default(),
+            &HashRegistry::default(),
+        );
+        assert_eq!(payload.operation_name, "addToPlaylist");
+        assert_eq!(
+            payload.extensions.persisted_query.sha256_hash,
+            constants::persistent_queries::ADD_TO_PLAYLIST
+        );
+    }
+}
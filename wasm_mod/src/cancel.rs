@@ -0,0 +1,26 @@
+//! A flag to stop the running job before its next request to Spotify.
+//!
+//! The flag is set by `cancel_current_job` from JS while the job is waiting for a response.
+//! WASM runs on a single thread, so the flag is a thread local, which also keeps `cargo test` threads apart.
+//! Every job clears it at the start, so a late click does not cancel the next job.
+
+use std::cell::Cell;
+
+thread_local! {
+    static CANCELLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Asks the running job to stop.
+pub(crate) fn cancel() {
+    CANCELLED.with(|v| v.set(true));
+}
+
+/// Clears the flag, e.g. at the start of a job.
+pub(crate) fn reset() {
+    CANCELLED.with(|v| v.set(false));
+}
+
+/// Returns true if the job should stop.
+pub(crate) fn is_cancelled() -> bool {
+    CANCELLED.with(|v| v.get())
+}
@@ -1,59 +1,603 @@
-use crate::BrowserRuntime;
 use std::collections::HashSet;
+use std::str::FromStr;
 
 // use std::time::Duration;
 use crate::{
     api_wrappers::{
-        add_tracks_to_playlist, fetch_album_tracks, fetch_lib_v3_items, fetch_playlist_tracks,
+        add_tracks_to_playlist, fetch_album_tracks, fetch_lib_v3_items, fetch_liked_tracks,
+        fetch_playlist_tracks, PlaylistItem,
     },
-    constants,
+    cancel, constants,
     constants::log,
-    report_progress,
+    hash_registry::HashRegistry,
+    models::{
+        generation_options::GenerationOptions,
+        job_state::{JobPhase, JobState},
+        selection::{Selection, SourceKind, TrackSource},
+        InsertPosition,
+    },
+    report_progress, storage,
+    strategy::{self, Candidates, SelectionStrategy},
+    transport::Transport,
+    Error, Result,
 };
-use rand::seq::SliceRandom;
+use futures::stream::{self, StreamExt};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Counts fetched albums or playlists to report the fetch rate to the user
+struct Throughput {
+    started_ms: u64,
+    fetched: usize,
+}
+
+impl Throughput {
+    fn new(now_ms: u64) -> Self {
+        Self {
+            started_ms: now_ms,
+            fetched: 0,
+        }
+    }
+
+    /// Counts one more fetched item and reports progress every `constants::THROUGHPUT_REPORT_INTERVAL` items.
+    fn report_every_interval(&mut self, what: &str, total: usize, now_ms: u64) {
+        self.fetched += 1;
+        if !self
+            .fetched
+            .is_multiple_of(constants::THROUGHPUT_REPORT_INTERVAL)
+        {
+            return;
+        }
+
+        // avoid division by zero if the clock did not move
+        let elapsed_secs = (now_ms.saturating_sub(self.started_ms) as f64 / 1000.0).max(0.001);
+        report_progress(&format!(
+            "Fetched {} of {total} {what} ({:.1} per second)",
+            self.fetched,
+            self.fetched as f64 / elapsed_secs
+        ));
+    }
+}
+
+/// Moves tracks already present in the target playlist from `tracks` to `duplicates`.
+fn remove_duplicates(
+    tracks: &mut Vec<String>,
+    target_playlist_tracks: &HashSet<String>,
+    duplicates: &mut HashSet<String>,
+) {
+    tracks.retain(|v| {
+        if target_playlist_tracks.contains(v) {
+            duplicates.insert(v.clone());
+            false
+        } else {
+            true
+        }
+    });
+}
+
+/// Where the new tracks go in the target playlist
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Placement {
+    #[default]
+    Bottom,
+    Top,
+    /// Right after the playlist item with this UID
+    AfterUid(String),
+    /// Spread evenly between the existing items
+    Interleave,
+}
+
+impl Placement {
+    /// Only adding to the bottom was captured in `samples/`.
+    /// The other placements need move types that were never seen in a real request.
+    pub(crate) fn is_verified(&self) -> bool {
+        matches!(self, Self::Bottom)
+    }
+}
+
+impl FromStr for Placement {
+    type Err = String;
+
+    /// Parses `bottom`, `top`, `interleave` or `after:<uid>`. An empty string is `bottom`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "" | "bottom" => Ok(Self::Bottom),
+            "top" => Ok(Self::Top),
+            "interleave" => Ok(Self::Interleave),
+            v => match v.strip_prefix("after:") {
+                Some(uid) if !uid.is_empty() => Ok(Self::AfterUid(uid.to_owned())),
+                _ => Err(format!("Unknown position for new tracks: {v}")),
+            },
+        }
+    }
+}
 
 /// Generates a random playlist of a predefined size out of everything stored in the user library.
-pub(crate) async fn generate_random_playlist(
+/// Albums and playlists that cannot be fetched are skipped unless the error is fatal.
+/// The state of the job is saved as it goes to resume it with `resume_job` if it stops half way.
+/// Returns a summary message for the user.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn generate_random_playlist<T: Transport>(
     auth_header_value: &str,
     token_header_value: &str,
     target_playlist_id: &str,
     user_uri: &str,
     number_of_tracks_to_add: usize,
-    runtime: &BrowserRuntime,
-) -> Result<String, String> {
-    report_progress("Eclectic work started");
-    report_progress("Fetching details of the target playlist");
+    placement: &Placement,
+    options: &GenerationOptions,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<String> {
+    let mut job = JobState::new(
+        target_playlist_id,
+        user_uri,
+        number_of_tracks_to_add,
+        placement.clone(),
+        options.clone(),
+        seed_or_random(options.seed),
+        transport.now_ms(),
+    );
+    job.resumable = true;
 
-    log!("User: {user_uri}, target playlist: {target_playlist_id}");
+    run_job(
+        auth_header_value,
+        token_header_value,
+        &mut job,
+        hashes,
+        transport,
+    )
+    .await
+}
 
-    // get details of the target playlist - tracks and the owner to see if we can add tracks to it
-    // it used to be possible to add tracks to collaborative playlists, but I can't find how it's done now
-    // spotify sucks.
-    // TODO: retrieve playlist name for logging
-    let (target_playlist_tracks, owner_uri) = match fetch_playlist_tracks(
+/// Resumes the job that stopped before it finished, e.g. because the browser terminated the service worker.
+/// The phases that were done are not repeated and the tracks that were written are not written again.
+/// Jobs that are too old or were tried too many times are discarded.
+/// Returns a summary message for the user.
+pub(crate) async fn resume_job<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<String> {
+    let mut job = match storage::job_state().await {
+        Some(v) => match serde_json::from_str::<JobState>(&v) {
+            Ok(v) => v,
+            Err(e) => {
+                log!("Invalid job state: {e}");
+                storage::save_job_state("").await;
+                return Ok("Nothing to resume".to_owned());
+            }
+        },
+        None => return Ok("Nothing to resume".to_owned()),
+    };
+
+    if job.is_expired(transport.now_ms()) {
+        log!(
+            "Discarding job for {}, started at: {}, attempts: {}",
+            job.target_playlist_id,
+            job.started_at,
+            job.attempts
+        );
+        storage::save_job_state("").await;
+        return Ok(
+            "Discarded the unfinished job because it is too old or failed too many times"
+                .to_owned(),
+        );
+    }
+    job.resumable = true;
+
+    log!(
+        "Resuming job for {}, phase: {:?}, written: {}, attempts: {}",
+        job.target_playlist_id,
+        job.phase,
+        job.written.len(),
+        job.attempts
+    );
+    report_progress("Resuming the unfinished job");
+
+    run_job(
         auth_header_value,
         token_header_value,
-        target_playlist_id,
-        1000,
-        runtime,
+        &mut job,
+        hashes,
+        transport,
+    )
+    .await
+}
+
+/// Returns true if the job that ended with this result should be resumed later.
+/// Only errors that may go away by themselves are worth another attempt, e.g. a network error or an expired token.
+/// Anything else, e.g. a missing playlist or a changed response schema, would fail the same way again.
+fn keep_job_state(result: &Result<String>) -> bool {
+    matches!(result, Err(e) if e.is_transient())
+}
+
+/// Runs the phases of the job that are not done yet.
+/// The saved state is kept only if the job stopped with a transient error to resume it later
+/// and is removed in all other cases.
+/// If the service worker is terminated half way, this function never returns and the state stays as it was last saved.
+async fn run_job<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    job: &mut JobState,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<String> {
+    job.attempts += 1;
+    save_job(job).await;
+
+    let result = match select_for_job(
+        auth_header_value,
+        token_header_value,
+        job,
+        hashes,
+        transport,
     )
     .await
     {
-        Some(v) => (v.tracks, v.owner_uri),
+        Ok(()) => {
+            write_for_job(
+                auth_header_value,
+                token_header_value,
+                job,
+                hashes,
+                transport,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+
+    if keep_job_state(&result) {
+        report_progress(
+            "The job stopped before it finished. The extension will try again shortly while Spotify is open in a tab.",
+        );
+    } else {
+        storage::save_job_state("").await;
+    }
+    report_seed(job.seed);
+
+    result
+}
+
+/// Returns the seed asked for or a new random one.
+fn seed_or_random(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| rand::thread_rng().gen_range(0..=constants::MAX_SEED))
+}
+
+/// Lets the user repeat the run with the same random choices, whether it succeeded or not.
+fn report_seed(seed: u64) {
+    log!("Seed: {seed}");
+    report_progress(&format!("Seed: {seed}"));
+}
+
+/// Saves the state of the job in the extension storage unless the job is not resumable.
+async fn save_job(job: &JobState) {
+    if !job.resumable {
+        return;
+    }
+
+    match serde_json::to_string(job) {
+        Ok(v) => storage::save_job_state(&v).await,
+        Err(e) => log!("Cannot serialize the job state: {e}"),
+    }
+}
+
+/// Writes the selected tracks to the target playlist one request at a time and saves the state after every request.
+/// Tracks that are already in the playlist are skipped, including the ones written by this job before it was resumed.
+/// Returns a summary message for the user.
+async fn write_for_job<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    job: &mut JobState,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<String> {
+    let target_playlist_id = job.target_playlist_id.clone();
+
+    // UIDs of the existing items are needed to insert new tracks between them
+    // the items fetched for the selection are reused, a resumed job fetches them again
+    let items = match job.target_items.take() {
+        Some(v) => v,
         None => {
-            // cannot proceed if the target playlist does not exist
-            return Err("Cannot fetch target playlist details from Spotify".to_owned());
+            fetch_playlist_tracks(
+                auth_header_value,
+                token_header_value,
+                &target_playlist_id,
+                None,
+                hashes,
+                transport,
+            )
+            .await?
+            .items
+        }
+    };
+
+    // this is done only once per job, before anything is written
+    if job.existing_uids.is_none() {
+        job.existing_uids = Some(items.iter().map(|v| v.uid.clone()).collect());
+        save_job(job).await;
+    }
+    let existing_uids = job
+        .existing_uids
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<HashSet<String>>();
+
+    // items that were not there before are the tracks written by this job, possibly by a request
+    // that finished after the state was last saved
+    let (existing_items, new_items): (Vec<PlaylistItem>, Vec<PlaylistItem>) = items
+        .into_iter()
+        .partition(|v| existing_uids.contains(&v.uid));
+    let selected_tracks = job
+        .selected
+        .iter()
+        .map(|v| v.track_id.clone())
+        .collect::<HashSet<String>>();
+    let mut written = job.written.iter().cloned().collect::<HashSet<String>>();
+    for track_id in new_items.into_iter().filter_map(|v| v.track_id) {
+        if selected_tracks.contains(&track_id) && written.insert(track_id.clone()) {
+            job.written.push(track_id);
         }
+    }
+
+    // tracks that are still in the playlist are never added twice
+    // the rest keep the order chosen by the selection strategy
+    let present_tracks = existing_items
+        .iter()
+        .filter_map(|v| v.track_id.as_ref())
+        .collect::<HashSet<&String>>();
+    let tracks_to_write = job
+        .selected
+        .iter()
+        .map(|v| &v.track_id)
+        .filter(|v| !written.contains(*v) && !present_tracks.contains(v))
+        .cloned()
+        .collect::<Vec<String>>();
+
+    // report the progress back to the user before writing to the playlist
+    report_progress(&format!(
+        "Adding {} tracks to the target playlist",
+        tracks_to_write.len()
+    ));
+
+    let lots = match job.placement.clone() {
+        Placement::Bottom => vec![(InsertPosition::Bottom, tracks_to_write)],
+        Placement::Top => vec![(InsertPosition::Top, tracks_to_write)],
+        Placement::AfterUid(uid) => vec![(InsertPosition::AfterUid(uid), tracks_to_write)],
+        Placement::Interleave => interleave(&existing_items, tracks_to_write),
     };
 
+    // every request is saved as soon as it is done for a resumed job to not add the same tracks again
+    let mut tracks_missed = 0;
+    'lots: for (position, tracks) in lots {
+        // every batch goes to the same position, so the last batch is sent first to keep the order
+        let mut batches = tracks
+            .chunks(constants::ADD_ITEMS_PER_REQUEST)
+            .collect::<Vec<&[String]>>();
+        if position.reverses_lots() {
+            batches.reverse();
+        }
+
+        for batch in batches {
+            if cancel::is_cancelled() {
+                break 'lots;
+            }
+
+            let tracks_added = add_tracks_to_playlist(
+                auth_header_value,
+                token_header_value,
+                &target_playlist_id,
+                batch.to_vec(),
+                &position,
+                hashes,
+                transport,
+            )
+            .await?;
+
+            // a batch is a single request, so either all or none of the tracks were added
+            if tracks_added == batch.len() {
+                job.written.extend_from_slice(batch);
+                save_job(job).await;
+            } else if !cancel::is_cancelled() {
+                tracks_missed += batch.len();
+            }
+        }
+    }
+    let tracks_added = job.written.len();
+
+    // the tracks that were not sent after the cancellation are not failures
+    if cancel::is_cancelled() {
+        cancel::reset();
+        return Ok(format!("Cancelled: added {tracks_added} tracks"));
+    }
+
+    // the mix is reported for the tracks that made it to the playlist
+    let mix = mix_summary(job)
+        .map(|v| format!(". {v}"))
+        .unwrap_or_default();
+
+    // failed batches are skipped, so the user should know how many tracks did not make it
+    if tracks_missed > 0 {
+        return Ok(format!(
+            "Done: added {tracks_added} tracks, {tracks_missed} could not be added{mix}"
+        ));
+    }
+
+    Ok(format!("Done: added {tracks_added} tracks{mix}"))
+}
+
+/// Compares the mix of sources of the written tracks with the mix asked for in the options.
+/// Returns None if no mix was asked for or nothing was written.
+fn mix_summary(job: &JobState) -> Option<String> {
+    let mix = job.options.source_mix?;
+    let written = job.written.iter().collect::<HashSet<&String>>();
+    let mut counts = [0; 3];
+    for track in job
+        .selected
+        .iter()
+        .filter(|v| written.contains(&v.track_id))
+    {
+        counts[track.source.kind().index()] += 1;
+    }
+    let total = counts.iter().sum::<usize>();
+    if total == 0 {
+        return None;
+    }
+
+    let achieved = SourceKind::ALL
+        .map(|v| format!("{}% {v}", (counts[v.index()] * 100 + total / 2) / total))
+        .join(", ");
+    let requested = mix.shares_pct().map(|v| format!("{v}%")).join(", ");
+
+    Some(format!("Mix: {achieved} (requested {requested})"))
+}
+
+/// Splits the tracks into lots to spread them evenly between the existing items.
+/// Every track goes into the middle of its share of the gaps between the items,
+/// e.g. 1 track goes into the middle of the playlist and 4 tracks go 2 before and 2 after a single item.
+/// Returns the lots in the playlist order with the position for each lot.
+fn interleave(
+    existing_items: &[PlaylistItem],
+    tracks: Vec<String>,
+) -> Vec<(InsertPosition, Vec<String>)> {
+    if existing_items.is_empty() {
+        return vec![(InsertPosition::Bottom, tracks)];
+    }
+
+    let gaps = existing_items.len() + 1;
+    let number_of_tracks = tracks.len();
+    let mut lots: Vec<(usize, Vec<String>)> = Vec::new();
+    for (idx, track) in tracks.into_iter().enumerate() {
+        // 0 is before the first item, 1 is after the first item, etc
+        let gap = (2 * idx + 1) * gaps / (2 * number_of_tracks);
+        match lots.last_mut() {
+            Some((last_gap, lot)) if *last_gap == gap => lot.push(track),
+            _ => lots.push((gap, vec![track])),
+        }
+    }
+
+    lots.into_iter()
+        .map(|(gap, lot)| {
+            let position = match gap {
+                0 => InsertPosition::Top,
+                v => InsertPosition::AfterUid(existing_items[v - 1].uid.clone()),
+            };
+            (position, lot)
+        })
+        .collect()
+}
+
+/// Selects random tracks from everything stored in the user library without writing anything.
+/// Albums and playlists that cannot be fetched are skipped unless the error is fatal.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn select_tracks<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    target_playlist_id: &str,
+    user_uri: &str,
+    number_of_tracks_to_add: usize,
+    options: &GenerationOptions,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<Selection> {
+    // the preview is not resumable, so its state is never saved
+    let mut job = JobState::new(
+        target_playlist_id,
+        user_uri,
+        number_of_tracks_to_add,
+        Placement::Bottom,
+        options.clone(),
+        seed_or_random(options.seed),
+        transport.now_ms(),
+    );
+
+    let result = select_for_job(
+        auth_header_value,
+        token_header_value,
+        &mut job,
+        hashes,
+        transport,
+    )
+    .await;
+    report_seed(job.seed);
+    result?;
+
+    Ok(Selection {
+        tracks: job.selected,
+        duplicates_skipped: job.duplicates.len(),
+        failed_sources: job.failed_sources,
+        seed: job.seed,
+    })
+}
+
+/// Counts selected tracks that came from albums, playlists or liked songs for reporting.
+fn count_selected(job: &JobState, is_from: impl Fn(&TrackSource) -> bool) -> usize {
+    job.selected.iter().filter(|v| is_from(&v.source)).count()
+}
+
+/// Fetches tracks for the job into the candidate pool until the strategy has enough of them
+/// and then selects the tracks out of the pool, skipping the phases the job has already done.
+/// The state of the job is saved after every phase.
+async fn select_for_job<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    job: &mut JobState,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<()> {
+    if job.phase == JobPhase::Writing {
+        return Ok(());
+    }
+
+    report_progress("Eclectic work started");
+    report_progress("Fetching details of the target playlist");
+
+    log!(
+        "User: {}, target playlist: {}",
+        job.user_uri,
+        job.target_playlist_id
+    );
+
+    // get details of the target playlist - tracks and the permissions to see if we can add tracks to it
+    // TODO: retrieve playlist name for logging
+    // cannot proceed if the target playlist does not exist
+    // all tracks are fetched, no matter how many, to avoid adding duplicates
+    let target_playlist = fetch_playlist_tracks(
+        auth_header_value,
+        token_header_value,
+        &job.target_playlist_id,
+        None,
+        hashes,
+        transport,
+    )
+    .await?;
+    let (target_playlist_tracks, access) = (target_playlist.tracks, target_playlist.access);
+
     report_progress("Target playlist details fetched");
 
-    // check if the playlis is owned by the current user
-    if user_uri != owner_uri {
-        log!("Playlist owner mismatch: {owner_uri}/{user_uri}");
-        return Err("Cannot add tracks to someone else's playlist. Try again with a playlist you created yourself.".to_owned());
+    // the playlist should be owned by the current user or be collaborative
+    if !access.can_edit(&job.user_uri) {
+        log!(
+            "Cannot edit the playlist: {access:?}, user: {}",
+            job.user_uri
+        );
+        return Err(Error::CannotEdit {
+            operation: constants::operations::PLAYLIST_TRACKS,
+            url: [
+                "https://open.spotify.com/playlist/",
+                &job.target_playlist_id,
+            ]
+            .concat(),
+            owner_uri: access.owner_uri,
+        });
     }
 
+    // the items are reused for writing if the job gets that far without stopping
+    job.target_items = Some(target_playlist.items);
+
     report_progress("Fetching target playlist tracks");
 
     let target_playlist_tracks = target_playlist_tracks
@@ -64,22 +608,127 @@ pub(crate) async fn generate_random_playlist(
         target_playlist_tracks.len()
     ));
 
-    report_progress("Fetching list of albums from My Library");
+    let strategy = strategy::for_options(&job.options);
+
+    if job.phase == JobPhase::Started {
+        find_sources(
+            auth_header_value,
+            token_header_value,
+            job,
+            &target_playlist_tracks,
+            hashes,
+            transport,
+        )
+        .await?;
+        job.phase = JobPhase::SourcesFound;
+        save_job(job).await;
+    }
+
+    fetch_from_sources(
+        auth_header_value,
+        token_header_value,
+        job,
+        &target_playlist_tracks,
+        strategy.as_ref(),
+        hashes,
+        transport,
+    )
+    .await?;
+
+    if job.failed_sources > 0 {
+        report_progress(&format!(
+            "Skipped {} albums or playlists that could not be fetched",
+            job.failed_sources
+        ));
+    }
+
+    // log!("Selected tracks:");
+    // log!(
+    //     "{}",
+    //     selected_tracks
+    //         .clone()
+    //         .into_iter()
+    //         .collect::<Vec<String>>()
+    //         .join("\n")
+    // );
+
+    // the pool is not needed after that and is not saved with the rest of the state
+    let pool = std::mem::take(&mut job.pool);
+    job.selected = strategy.select(
+        &pool.iter().collect::<Vec<&Candidates>>(),
+        job.number_of_tracks_to_add,
+        &mut job.rng,
+    );
+
+    log!(
+        "Selected tracks: {} out of {} sources",
+        job.selected.len(),
+        pool.len()
+    );
+    report_progress(&format!(
+        "Selected {} tracks from liked songs",
+        count_selected(job, |v| *v == TrackSource::LikedSongs)
+    ));
+    report_progress(&format!(
+        "Selected {} tracks from albums",
+        count_selected(job, |v| matches!(v, TrackSource::Album { .. }))
+    ));
+    report_progress(&format!(
+        "Selected {} tracks from playlists",
+        count_selected(job, |v| matches!(v, TrackSource::Playlist { .. }))
+    ));
+
+    report_progress(&format!(
+        "Skipped {} tracks already in the target playlist",
+        job.duplicates.len()
+    ));
+
+    job.phase = JobPhase::Writing;
+    save_job(job).await;
+
+    Ok(())
+}
+
+/// Fetches the lists of albums and playlists in random order and adds liked songs to the pool.
+async fn find_sources<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    job: &mut JobState,
+    target_playlist_tracks: &HashSet<String>,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<()> {
+    // the sources the user turned off are not fetched at all
+    let options = job.options.clone();
 
     // collect all album IDs
-    let all_albums =
-        fetch_lib_v3_items(auth_header_value, token_header_value, "Albums", runtime).await;
+    let all_albums = if options.include_albums {
+        report_progress("Fetching list of albums from My Library");
+        fetch_lib_v3_items(
+            auth_header_value,
+            token_header_value,
+            "Albums",
+            options.library_order,
+            hashes,
+            transport,
+        )
+        .await?
+    } else {
+        Vec::new()
+    };
 
-    // remove the repetitive prefix
-    let mut all_albums = all_albums
+    // remove the repetitive prefix and the excluded albums
+    // the limit applies before the shuffle to take the first N in the library order
+    let all_albums = all_albums
         .into_iter()
         // .take(5) // uncomment for debugging to limit the number of albums
         .map(|v| v.replace(constants::ID_PREFIX_ALBUM, ""))
+        .filter(|v| !options.exclude_sources.contains(v))
+        .take(options.max_albums.unwrap_or(usize::MAX))
         .collect::<Vec<String>>();
 
     // log!("{}", all_albums.join("\n"));
     report_progress(&format!("Found {} albums in the library", all_albums.len()));
-    report_progress("Fetching list of playlists from My Library");
 
     // repeat the same for playlists - collect all playlist IDs
 
@@ -88,20 +737,37 @@ pub(crate) async fn generate_random_playlist(
     // operationName: libraryV3
     // variables: {"filters":["Playlists"],"order":null,"textFilter":"","features":["LIKED_SONGS","YOUR_EPISODES"],"limit":50,"offset":6,"flatten":false,"expandedFolders":[],"folderUri":null,"includeFoldersWhenFlattening":true,"withCuration":false}
 
-    let all_playlists =
-        fetch_lib_v3_items(auth_header_value, token_header_value, "Playlists", runtime).await;
+    let all_playlists = if options.include_playlists {
+        report_progress("Fetching list of playlists from My Library");
+        fetch_lib_v3_items(
+            auth_header_value,
+            token_header_value,
+            "Playlists",
+            options.library_order,
+            hashes,
+            transport,
+        )
+        .await?
+    } else {
+        Vec::new()
+    };
 
-    // remove the repetitive prefix and exclude the current playlist
-    let mut all_playlists = all_playlists
+    // remove the repetitive prefix and exclude the current playlist and the excluded ones
+    // Liked Songs come up as a pseudo-playlist `spotify:collection:tracks` and are fetched separately
+    let all_playlists = all_playlists
         .into_iter()
         .filter_map(|v| {
+            if !v.starts_with(constants::ID_PREFIX_PLAYLIST) {
+                return None;
+            }
             let v = v.replace(constants::ID_PREFIX_PLAYLIST, "");
-            if v == target_playlist_id {
+            if v == job.target_playlist_id || options.exclude_sources.contains(&v) {
                 None
             } else {
                 Some(v)
             }
         })
+        .take(options.max_playlists.unwrap_or(usize::MAX))
         .collect::<Vec<String>>();
 
     // log!("{}", all_playlists.join("\n"));
@@ -110,221 +776,1035 @@ pub(crate) async fn generate_random_playlist(
         all_playlists.len()
     ));
 
-    // randomize the list of albums and playlists
-    let mut rng = rand::thread_rng();
-    all_playlists.shuffle(&mut rng);
-    all_albums.shuffle(&mut rng);
-
-    // a list of tracks selected for the addition to the new playlist
-    let mut selected_tracks: HashSet<String> = HashSet::new();
-
-    // a list of tracks per that were not selected
-    let mut stashed_tracks: HashSet<String> = HashSet::new();
+    let mut liked_tracks = if options.include_liked_songs {
+        report_progress("Fetching liked songs from My Library");
+        match fetch_liked_tracks(auth_header_value, token_header_value, hashes, transport).await {
+            Ok(v) => v,
+            Err(e) if e.is_fatal() => return Err(e),
+            Err(e) => {
+                // not critical - there are still albums and playlists to choose from
+                log!("Skipping liked songs: {e:?}");
+                job.failed_sources += 1;
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
 
-    // go thru all albums
     report_progress(&format!(
-        "Selecting random tracks from {} albums",
-        all_albums.len()
+        "Found {} liked songs in the library",
+        liked_tracks.len()
     ));
-    for album_id in all_albums {
-        // get album tracks, shuffle and add top N tracks to the selected list
-        let mut album_tracks = fetch_album_tracks(
-            auth_header_value,
-            token_header_value,
-            &album_id,
-            50,
-            runtime,
-        )
-        .await;
 
-        if album_tracks.is_empty() {
-            log!("Empty album {album_id}");
-            continue;
-        } else if album_tracks.len() <= constants::MIN_TRACKS_PER_ALBUM {
-            // the album is too small and all tracks should be added
-            log!(
-                "Sel: {}, stash: {}, adding all {} tracks from album {album_id}",
-                selected_tracks.len(),
-                stashed_tracks.len(),
-                album_tracks.len(),
-            );
-            let _: Vec<_> = album_tracks
-                .into_iter()
-                .map(|v| selected_tracks.insert(v))
-                .collect();
-        } else {
-            // select N random tracks
-            album_tracks.shuffle(&mut rng);
-            let _: Vec<_> = album_tracks
-                .drain(..constants::MIN_TRACKS_PER_ALBUM)
-                .map(|v| selected_tracks.insert(v))
-                .collect();
-
-            // stash the remaining tracks
-            let _: Vec<_> = album_tracks
+    // tracks already in the target playlist never get into the pool
+    // so that other tracks can make up for them
+    remove_duplicates(
+        &mut liked_tracks,
+        target_playlist_tracks,
+        &mut job.duplicates,
+    );
+
+    // mix albums and playlists in random order, the strategy shuffles the tracks
+    let mut all_sources = all_albums
+        .into_iter()
+        .map(|id| TrackSource::Album { id })
+        .chain(
+            all_playlists
                 .into_iter()
-                .map(|v| stashed_tracks.insert(v))
-                .collect();
-            log!(
-                "Sel: {}, stash: {}, added {} tracks from album {album_id}",
-                selected_tracks.len(),
-                stashed_tracks.len(),
-                constants::MIN_TRACKS_PER_ALBUM,
-            );
-        }
+                .map(|id| TrackSource::Playlist { id }),
+        )
+        .collect::<Vec<TrackSource>>();
+    all_sources.shuffle(&mut job.rng);
 
-        // exit if there are enough tracks for the playlist
-        if selected_tracks.len() >= number_of_tracks_to_add {
-            break;
-        }
+    // liked songs go first, the strategy decides how many of them to take
+    if !liked_tracks.is_empty() {
+        job.pool.push(Candidates {
+            source: TrackSource::LikedSongs,
+            tracks: liked_tracks,
+        });
     }
 
-    log!(
-        "Selected tracks: {}, stash tracks: {}",
-        selected_tracks.len(),
-        stashed_tracks.len(),
-    );
+    job.sources = all_sources;
+
+    Ok(())
+}
+
+/// Fetches tracks of the albums and playlists of the job into the pool until the strategy has enough of them.
+/// Albums and playlists are fetched in the same random order, so neither kind crowds out the other.
+async fn fetch_from_sources<T: Transport>(
+    auth_header_value: &str,
+    token_header_value: &str,
+    job: &mut JobState,
+    target_playlist_tracks: &HashSet<String>,
+    strategy: &dyn SelectionStrategy,
+    hashes: &HashRegistry,
+    transport: &T,
+) -> Result<()> {
+    let max_tracks_per_playlist = job.options.max_tracks_per_playlist;
 
-    let selected_album_tracks_count = selected_tracks.len();
     report_progress(&format!(
-        "Selected {selected_album_tracks_count} tracks from albums"
+        "Selecting random tracks from {} albums and playlists",
+        job.sources.len()
     ));
+    // there is no need to fetch anything if liked songs provided enough tracks
+    let all_sources = if strategy.has_enough(
+        &job.pool.iter().collect::<Vec<&Candidates>>(),
+        job.number_of_tracks_to_add,
+    ) {
+        Vec::new()
+    } else {
+        job.sources.clone()
+    };
+    // fetch several sources at a time, but process them in the order of the list
+    // for the same seed to select the same tracks no matter which response comes first
+    // dropping the stream on early exit cancels the requests still in flight
+    let total_sources = all_sources.len();
+    let mut throughput = Throughput::new(transport.now_ms());
+    let mut source_stream = stream::iter(all_sources)
+        .map(|source| async move {
+            // albums have no owner to check
+            let tracks = match &source {
+                TrackSource::Album { id } => fetch_album_tracks(
+                    auth_header_value,
+                    token_header_value,
+                    id,
+                    // albums are fetched in full for the random tracks to come from the whole album
+                    None,
+                    hashes,
+                    transport,
+                )
+                .await
+                .map(|v| (v, None)),
+                TrackSource::Playlist { id } => fetch_playlist_tracks(
+                    auth_header_value,
+                    token_header_value,
+                    id,
+                    Some(max_tracks_per_playlist),
+                    hashes,
+                    transport,
+                )
+                .await
+                .map(|v| (v.tracks, Some(v.access.owner_uri))),
+                // liked songs are fetched separately and are never in the list
+                TrackSource::LikedSongs => Ok((Vec::new(), None)),
+            };
+            (source, tracks)
+        })
+        .buffered(constants::MAX_CONCURRENT_FETCHES);
 
-    // go thru all playlists
-    // TODO: merge this with the album loop, but I do not know how to do it in terms of track priorities and what to pick from where in what order
-    // to make it a more representative sample. Large playlists may dominate and skew the results.
-    report_progress("Selecting random playlist tracks");
-    for playlist_id in all_playlists {
-        // get album tracks, shuffle and add top N tracks to the selected list
-        let (mut tracks, owner_uri) = match fetch_playlist_tracks(
-            auth_header_value,
-            token_header_value,
-            &playlist_id,
-            constants::MAX_TRACKS_PER_PLAYLIST,
-            runtime,
-        )
-        .await
-        {
-            Some(v) => (v.tracks, v.owner_uri),
-            None => {
+    while let Some((source, tracks)) = source_stream.next().await {
+        throughput.report_every_interval("albums and playlists", total_sources, transport.now_ms());
+
+        let (mut tracks, owner_uri) = match tracks {
+            Ok(v) => v,
+            Err(e) if e.is_fatal() => return Err(e),
+            Err(e) => {
                 // ignore the failure - not critical
-                // TODO: add a counter
+                log!("Skipping {source:?}: {e:?}");
+                job.failed_sources += 1;
                 continue;
             }
         };
+        if let Some(owner_uri) = owner_uri {
+            if job.options.own_playlists_only && owner_uri != job.user_uri {
+                log!("Skipping {source:?} owned by {owner_uri}");
+                continue;
+            }
+        }
+        remove_duplicates(&mut tracks, target_playlist_tracks, &mut job.duplicates);
 
         if tracks.is_empty() {
-            log!("Empty playlist {playlist_id}");
+            log!("Empty {source:?}");
             continue;
-        } else if tracks.len() <= constants::MIN_TRACKS_PER_ALBUM {
-            // the playlist is too small and all tracks should be added
-            log!(
-                "Sel: {}, stash: {}, adding all {} tracks from playlist {playlist_id}, owner: {owner_uri}",
-                selected_tracks.len(),
-                stashed_tracks.len(),
-                tracks.len(),
-            );
-            let _: Vec<_> = tracks
-                .into_iter()
-                .map(|v| selected_tracks.insert(v))
-                .collect();
-        } else {
-            // select N random tracks
-            tracks.shuffle(&mut rng);
-            let _: Vec<_> = tracks
-                .drain(..constants::MIN_TRACKS_PER_ALBUM)
-                .map(|v| selected_tracks.insert(v))
-                .collect();
-
-            // stash the remaining tracks
-            let _: Vec<_> = tracks
-                .into_iter()
-                .map(|v| stashed_tracks.insert(v))
-                .collect();
-            log!(
-                "Sel: {}, stash: {}, added {} tracks from playlist {playlist_id}, owner: {owner_uri}",
-                selected_tracks.len(),
-                stashed_tracks.len(),
-                constants::MIN_TRACKS_PER_ALBUM,
-            );
         }
+        log!(
+            "Pool: {} sources, added {} tracks from {source:?}",
+            job.pool.len(),
+            tracks.len(),
+        );
+        job.pool.push(Candidates { source, tracks });
 
         // exit if there are enough tracks for the playlist
-        if selected_tracks.len() >= number_of_tracks_to_add {
+        if strategy.has_enough(
+            &job.pool.iter().collect::<Vec<&Candidates>>(),
+            job.number_of_tracks_to_add,
+        ) {
             break;
         }
     }
 
-    log!(
-        "Selected tracks: {}, stashed tracks: {}",
-        selected_tracks.len(),
-        stashed_tracks.len()
-    );
+    log!("Pool: {} sources", job.pool.len());
 
-    // figure out how many tracks were added from playlists for reporting
-    let selected_playlist_tracks_count = selected_tracks.len() - selected_album_tracks_count;
-    report_progress(&format!(
-        "Selected {selected_playlist_tracks_count} tracks from playlists"
-    ));
+    Ok(())
+}
 
-    // log!("Selected tracks:");
-    // log!(
-    //     "{}",
-    //     selected_tracks
-    //         .clone()
-    //         .into_iter()
-    //         .collect::<Vec<String>>()
-    //         .join("\n")
-    // );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{generation_options::SourceMix, selection::SelectedTrack};
+    use crate::transport::CannedTransport;
+    use futures::executor::block_on;
+
+    /// A library built out of `samples/` with the target playlist owned by `spotify:user:onebro.me`.
+    /// Library pages after the first one are empty.
+    pub(crate) fn sample_library() -> CannedTransport {
+        CannedTransport::default()
+            .respond_when(
+                constants::operations::PLAYLIST_TRACKS,
+                &["3h9rkMXa434AeAIDdA5Dd2"],
+                include_str!("../samples/fetch-my-playlist-resp.json"),
+            )
+            .respond(
+                constants::operations::PLAYLIST_TRACKS,
+                include_str!("../samples/fetch-playlist-resp.json"),
+            )
+            .respond_when(
+                constants::operations::ALBUMS_PLAYLISTS,
+                &["Albums", r#""offset":0"#],
+                include_str!("../samples/libraryV3-albums-resp.json"),
+            )
+            .respond_when(
+                constants::operations::ALBUMS_PLAYLISTS,
+                &["Playlists", r#""offset":0"#],
+                include_str!("../samples/libraryV3-playlists-resp.json"),
+            )
+            .respond(
+                constants::operations::ALBUMS_PLAYLISTS,
+                r#"{"data":{"me":{"libraryV3":{"items":[],"totalCount":0}}}}"#,
+            )
+            .respond(
+                constants::operations::ALBUM_TRACKS,
+                include_str!("../samples/getAlbum-resp.json"),
+            )
+            .respond_when(
+                constants::operations::LIKED_TRACKS,
+                &[r#""offset":0"#],
+                include_str!("../samples/fetchLibraryTracks-resp.json"),
+            )
+            .respond(
+                constants::operations::LIKED_TRACKS,
+                r#"{"data":{"me":{"library":{"tracks":{"items":[],"totalCount":0}}}}}"#,
+            )
+            .respond(
+                constants::operations::ADD_TO_PLAYLIST,
+                include_str!("../samples/add-track.json"),
+            )
+    }
+
+    #[test]
+    fn generate_from_sample_library() {
+        let transport = sample_library();
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            100,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(result.is_ok(), "{result:?}");
+        let added = transport
+            .requests_for(constants::operations::ADD_TO_PLAYLIST)
+            .len();
+        assert!(added > 0, "nothing was written to the playlist");
+    }
+
+    #[test]
+    fn report_tracks_that_could_not_be_added() {
+        let transport = CannedTransport::default()
+            .respond_with(constants::operations::ADD_TO_PLAYLIST, &[], 404, &[], "")
+            .then(sample_library());
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            3,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        assert_eq!(result, "Done: added 0 tracks, 3 could not be added");
+    }
+
+    #[test]
+    fn interleave_evenly() {
+        let tracks = |n: usize| (0..n).map(|v| format!("t{v}")).collect::<Vec<String>>();
+        let items = (1..=4)
+            .map(|v| PlaylistItem {
+                uid: format!("u{v}"),
+                track_id: Some("t".to_owned()),
+            })
+            .collect::<Vec<PlaylistItem>>();
+
+        // a single track goes into the middle
+        assert_eq!(
+            interleave(&items, tracks(1)),
+            vec![(InsertPosition::AfterUid("u2".to_owned()), tracks(1))]
+        );
+
+        // more tracks than items share the gaps
+        assert_eq!(
+            interleave(&items[..1], tracks(4)),
+            vec![
+                (InsertPosition::Top, vec!["t0".to_owned(), "t1".to_owned()]),
+                (
+                    InsertPosition::AfterUid("u1".to_owned()),
+                    vec!["t2".to_owned(), "t3".to_owned()]
+                )
+            ]
+        );
+
+        // one track per gap
+        let lots = interleave(&items, tracks(5));
+        assert_eq!(lots.len(), 5);
+        assert_eq!(lots[0].0, InsertPosition::Top);
+        assert_eq!(lots[4].0, InsertPosition::AfterUid("u4".to_owned()));
+
+        // an empty playlist
+        assert_eq!(
+            interleave(&[], tracks(3)),
+            vec![(InsertPosition::Bottom, tracks(3))]
+        );
+    }
+
+    #[test]
+    fn parse_placement() {
+        assert_eq!("".parse::<Placement>().unwrap(), Placement::Bottom);
+        assert_eq!("top".parse::<Placement>().unwrap(), Placement::Top);
+        assert_eq!(
+            "interleave".parse::<Placement>().unwrap(),
+            Placement::Interleave
+        );
+        assert_eq!(
+            "after:b7ba08bda4cbbd8e".parse::<Placement>().unwrap(),
+            Placement::AfterUid("b7ba08bda4cbbd8e".to_owned())
+        );
+        assert!("after:".parse::<Placement>().is_err());
+        assert!("middle".parse::<Placement>().is_err());
+
+        // only the bottom of the playlist is in the samples
+        assert!(Placement::Bottom.is_verified());
+        assert!(!Placement::Top.is_verified());
+        assert!(!Placement::Interleave.is_verified());
+        assert!(!Placement::AfterUid("b7ba08bda4cbbd8e".to_owned()).is_verified());
+    }
+
+    #[test]
+    fn interleave_with_existing_tracks() {
+        let transport = sample_library();
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            10,
+            &Placement::Interleave,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        // the last album is not cut short, so the number of tracks comes from the result
+        let tracks_added = result
+            .unwrap()
+            .trim_start_matches("Done: added ")
+            .trim_end_matches(" tracks")
+            .parse::<usize>()
+            .unwrap();
+        assert!(tracks_added >= 10);
+        // one request per track because there are more gaps than tracks
+        let added = transport.requests_for(constants::operations::ADD_TO_PLAYLIST);
+        assert_eq!(added.len(), tracks_added);
+        assert!(added
+            .iter()
+            .all(|v| v.variables.contains(r#""moveType":"AFTER_UID""#)));
+    }
+
+    #[test]
+    fn select_tracks_without_writing() {
+        let transport = sample_library();
+
+        let selection = block_on(select_tracks(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            100,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        assert!(transport
+            .requests_for(constants::operations::ADD_TO_PLAYLIST)
+            .is_empty());
+        // the sample library has fewer unique tracks than requested
+        assert!(!selection.tracks.is_empty() && selection.tracks.len() <= 100);
+        assert!(selection
+            .tracks
+            .iter()
+            .any(|v| v.source == TrackSource::LikedSongs));
+        assert!(selection
+            .tracks
+            .iter()
+            .any(|v| matches!(v.source, TrackSource::Album { .. })));
 
-    // add tracks from the stash if the selected list is not long enough
-    if selected_tracks.len() < number_of_tracks_to_add {
-        let number_to_add_from_stash = number_of_tracks_to_add - selected_tracks.len();
-        log!("Adding {number_to_add_from_stash} from stash");
-        for stashed_track in stashed_tracks.into_iter().take(number_to_add_from_stash) {
-            selected_tracks.insert(stashed_track);
+        // this is what the popup gets
+        let json = serde_json::to_value(&selection).unwrap();
+        assert!(json["tracks"][0]["trackId"].is_string());
+        assert!(json["tracks"][0]["source"]["type"].is_string());
+        assert_eq!(json["duplicatesSkipped"], 0);
+    }
+
+    #[test]
+    fn select_only_from_sources_enabled_in_options() {
+        let transport = sample_library();
+        let options = GenerationOptions {
+            include_albums: false,
+            include_liked_songs: false,
+            ..Default::default()
+        };
+
+        let selection = block_on(select_tracks(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            100,
+            &options,
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        assert!(transport
+            .requests_for(constants::operations::ALBUM_TRACKS)
+            .is_empty());
+        assert!(transport
+            .requests_for(constants::operations::LIKED_TRACKS)
+            .is_empty());
+        let requests = transport.requests_for(constants::operations::ALBUMS_PLAYLISTS);
+        assert!(requests.iter().all(|v| !v.variables.contains("Albums")));
+        assert!(requests[0]
+            .variables
+            .contains(r#""order":"Recently Added""#));
+        assert!(!selection.tracks.is_empty());
+        assert!(selection
+            .tracks
+            .iter()
+            .all(|v| matches!(v.source, TrackSource::Playlist { .. })));
+    }
+
+    #[test]
+    fn reject_playlists_owned_by_others() {
+        let transport = sample_library();
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:someone-else",
+            100,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(
+            matches!(result, Err(Error::CannotEdit { .. })),
+            "{result:?}"
+        );
+        assert!(transport
+            .requests_for(constants::operations::ADD_TO_PLAYLIST)
+            .is_empty());
+    }
+
+    #[test]
+    fn add_to_collaborative_playlists_owned_by_others() {
+        let target = include_str!("../samples/fetch-my-playlist-resp.json")
+            .replace(r#""collaborative": false"#, r#""collaborative": true"#);
+        let transport = CannedTransport::default()
+            .respond_when(
+                constants::operations::PLAYLIST_TRACKS,
+                &["3h9rkMXa434AeAIDdA5Dd2"],
+                &target,
+            )
+            .then(sample_library());
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:someone-else",
+            100,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(result.is_ok(), "{result:?}");
+        assert!(!transport
+            .requests_for(constants::operations::ADD_TO_PLAYLIST)
+            .is_empty());
+    }
+
+    #[test]
+    fn skip_missing_albums_but_stop_on_auth_errors() {
+        let transport = CannedTransport::default()
+            .respond_with(constants::operations::ALBUM_TRACKS, &[], 404, &[], "")
+            .times(1)
+            .respond_with(constants::operations::ALBUM_TRACKS, &[], 401, &[], "")
+            .then(sample_library());
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            100,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(matches!(result, Err(Error::Auth { .. })), "{result:?}");
+        // the requests already in flight may complete after the error
+        assert!(
+            transport
+                .requests_for(constants::operations::ALBUM_TRACKS)
+                .len()
+                < 2 + constants::MAX_CONCURRENT_FETCHES
+        );
+        assert!(transport
+            .requests_for(constants::operations::ADD_TO_PLAYLIST)
+            .is_empty());
+    }
+
+    #[test]
+    fn skip_tracks_already_in_the_target_playlist() {
+        // album tracks on a page of the target playlist past the first 1000 tracks
+        let album_tracks = ["1Gb2xAWNemgLXxufBy5qvE", "1iVM8aOM4wPNMoTWkFWpvj"];
+        let target_page = include_str!("../samples/fetch-my-playlist-resp.json")
+            .replace("46WtLgKPo8b2vVMPh0CjpE", album_tracks[0])
+            .replace("6IqL3kTVLsBTjmmfEpJRHT", album_tracks[1]);
+        let transport = CannedTransport::default()
+            .respond_when(
+                constants::operations::PLAYLIST_TRACKS,
+                &["3h9rkMXa434AeAIDdA5Dd2", r#""offset":1000"#],
+                &target_page,
+            )
+            .then(sample_library());
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            100,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(result.is_ok(), "{result:?}");
+        let added = transport.requests_for(constants::operations::ADD_TO_PLAYLIST);
+        assert!(!added.is_empty(), "nothing was written to the playlist");
+        assert!(added.iter().all(|v| album_tracks
+            .iter()
+            .all(|track| !v.variables.contains(track))));
+    }
+
+    #[test]
+    fn stop_fetching_when_enough_tracks_are_selected() {
+        let transport = sample_library();
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            3,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(result.is_ok(), "{result:?}");
+        // the library has 50 albums and 25 playlists, but two of them are enough for 3 tracks
+        // plus the ones already in flight when the second one arrived
+        let fetched = transport
+            .requests_for(constants::operations::ALBUM_TRACKS)
+            .len()
+            + transport
+                .requests_for(constants::operations::PLAYLIST_TRACKS)
+                .iter()
+                .filter(|v| !v.variables.contains("3h9rkMXa434AeAIDdA5Dd2"))
+                .count();
+        assert!(
+            fetched <= constants::MAX_CONCURRENT_FETCHES + 1,
+            "{fetched}"
+        );
+    }
+
+    #[test]
+    fn honor_the_source_mix() {
+        let transport = sample_library();
+        let options = GenerationOptions {
+            source_mix: Some(SourceMix {
+                liked_songs_pct: 50,
+                albums_pct: 30,
+                playlists_pct: 20,
+            }),
+            ..Default::default()
+        };
+
+        let selection = block_on(select_tracks(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            10,
+            &options,
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        let count = |kind: SourceKind| {
+            selection
+                .tracks
+                .iter()
+                .filter(|v| v.source.kind() == kind)
+                .count()
+        };
+        assert_eq!(count(SourceKind::LikedSongs), 5);
+        assert_eq!(count(SourceKind::Albums), 3);
+        assert_eq!(count(SourceKind::Playlists), 2);
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            10,
+            &Placement::Bottom,
+            &options,
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+        assert!(result.ends_with("(requested 50%, 30%, 20%)"), "{result}");
+    }
+
+    fn select_with_seed(seed: u64) -> Selection {
+        let options = GenerationOptions {
+            seed: Some(seed),
+            ..Default::default()
+        };
+
+        block_on(select_tracks(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            5,
+            &options,
+            &HashRegistry::default(),
+            &sample_library(),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn repeat_the_selection_with_the_same_seed() {
+        let selection = select_with_seed(42);
+
+        // this changes only if the selection logic or the order of random calls changes
+        assert_eq!(
+            selection
+                .tracks
+                .iter()
+                .map(|v| v.track_id.as_str())
+                .collect::<Vec<&str>>(),
+            [
+                "0uSfUZrGHvuqm1Yz4I51qC",
+                "68CFmjw8xUSiiRvJvWR3SV",
+                "15SqfVDeINiOECY9juEzVo",
+                "42Oaq8G5o8uKvokfH8HTLw",
+                "1hk0kLxZ9RVwpJxtsyR9uE"
+            ]
+        );
+        assert_eq!(selection.seed, 42);
+        assert_eq!(select_with_seed(42).tracks, selection.tracks);
+        assert_ne!(select_with_seed(43).tracks, selection.tracks);
+    }
+
+    #[test]
+    fn fetch_albums_and_playlists_in_one_random_order() {
+        let transport = sample_library();
+        let options = GenerationOptions {
+            include_liked_songs: false,
+            ..Default::default()
+        };
+
+        let selection = block_on(select_tracks(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            100,
+            &options,
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        // 50 albums alone would be enough, but playlists are not left until the albums run out
+        assert!(!selection.tracks.is_empty());
+        assert!(!transport
+            .requests_for(constants::operations::ALBUM_TRACKS)
+            .is_empty());
+        assert!(transport
+            .requests_for(constants::operations::PLAYLIST_TRACKS)
+            .iter()
+            .any(|v| !v.variables.contains("3h9rkMXa434AeAIDdA5Dd2")));
+    }
+
+    /// Cancels the job once the first request for the operation is sent,
+    /// as if the user clicked the button while it was in flight
+    struct CancelAfter {
+        inner: CannedTransport,
+        operation: &'static str,
+    }
+
+    impl Transport for CancelAfter {
+        async fn fetch(
+            &self,
+            request: crate::transport::HttpRequest<'_>,
+        ) -> std::result::Result<crate::transport::HttpResponse, String> {
+            let cancel = request.url.contains(self.operation)
+                || request
+                    .body
+                    .as_deref()
+                    .is_some_and(|v| v.contains(self.operation));
+            let resp = self.inner.fetch(request).await;
+            if cancel {
+                cancel::cancel();
+            }
+            resp
+        }
+
+        async fn sleep(&self, duration: std::time::Duration) {
+            self.inner.sleep(duration).await
+        }
+
+        fn now_ms(&self) -> u64 {
+            self.inner.now_ms()
         }
     }
 
-    log!(
-        "Selected tracks after adding from stash: {}",
-        selected_tracks.len(),
-    );
+    #[test]
+    fn stop_writing_when_cancelled() {
+        let transport = CancelAfter {
+            inner: sample_library(),
+            operation: constants::operations::ADD_TO_PLAYLIST,
+        };
 
-    // remove duplicates already present in the target playlist
-    // TODO: this does not work because we get only a few tracks from a playlist and this list doesn't have them all
-    let duplicate_tracks = target_playlist_tracks
-        .intersection(&selected_tracks)
-        .cloned()
-        .collect::<Vec<String>>();
-    log!(
-        "Removing {} selected tracks already in the target playlist",
-        duplicate_tracks.len()
-    );
-    for duplicate_track in duplicate_tracks {
-        selected_tracks.remove(&duplicate_track);
+        // interleaving sends a request per track
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            10,
+            &Placement::Interleave,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert_eq!(result.unwrap(), "Cancelled: added 1 tracks");
+        assert_eq!(
+            transport
+                .inner
+                .requests_for(constants::operations::ADD_TO_PLAYLIST)
+                .len(),
+            1
+        );
+        assert!(!cancel::is_cancelled());
     }
 
-    let selected_tracks = selected_tracks.into_iter().collect::<Vec<String>>();
+    /// The sample target playlist with only its two items: `Relief` and then `Unwind`
+    fn two_item_playlist() -> CannedTransport {
+        CannedTransport::default().respond(
+            constants::operations::PLAYLIST_TRACKS,
+            &include_str!("../samples/fetch-my-playlist-resp.json")
+                .replace(r#""totalCount": 5012"#, r#""totalCount": 2"#),
+        )
+    }
 
-    // report the progress back to the user before writing to the playlist
-    let msg = [
-        "Adding ",
-        &selected_tracks.len().to_string(),
-        "tracks to the target playlist",
-    ]
-    .concat();
-    report_progress(&msg);
-
-    // add selected tracks to the back of the current playlist
-    let tracks_added = add_tracks_to_playlist(
-        auth_header_value,
-        token_header_value,
-        target_playlist_id,
-        selected_tracks,
-        runtime,
-    )
-    .await;
+    #[test]
+    fn keep_job_state_only_after_transient_errors() {
+        let url = "https://api-partner.spotify.com/pathfinder/v1/query".to_owned();
+        let operation = constants::operations::ADD_TO_PLAYLIST;
+        let kept = [
+            Error::Network {
+                operation,
+                url: url.clone(),
+                detail: "offline".to_owned(),
+            },
+            Error::Http {
+                operation,
+                url: url.clone(),
+                status: 503,
+            },
+            Error::Auth {
+                operation,
+                url: url.clone(),
+                status: 401,
+            },
+            Error::RateLimited {
+                operation,
+                url: url.clone(),
+                retry_after: std::time::Duration::ZERO,
+            },
+        ];
+        let cleared = [
+            Error::Http {
+                operation,
+                url: url.clone(),
+                status: 404,
+            },
+            Error::Schema {
+                operation,
+                url: url.clone(),
+                detail: "missing field".to_owned(),
+            },
+            Error::GraphQl {
+                operation,
+                url: url.clone(),
+                messages: "Bad request".to_owned(),
+            },
+            Error::PersistedQueryNotFound {
+                operation,
+                url: url.clone(),
+            },
+            Error::CannotEdit {
+                operation,
+                url: url.clone(),
+                owner_uri: "spotify:user:someone-else".to_owned(),
+            },
+            Error::Cancelled {
+                operation,
+                url: url.clone(),
+            },
+        ];
 
-    Ok(format!("Done: added {tracks_added} tracks"))
+        for e in kept {
+            assert!(keep_job_state(&Err(e.clone())), "{e:?}");
+        }
+        for e in cleared {
+            assert!(!keep_job_state(&Err(e.clone())), "{e:?}");
+        }
+        assert!(!keep_job_state(&Ok("Done: added 3 tracks".to_owned())));
+    }
+
+    #[test]
+    fn resume_writing_after_the_job_stopped() {
+        let transport = CannedTransport::default()
+            .respond_with(constants::operations::ADD_TO_PLAYLIST, &[], 401, &[], "")
+            .then(sample_library());
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            10,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert!(matches!(result, Err(Error::Auth { .. })), "{result:?}");
+        let job =
+            serde_json::from_str::<JobState>(&block_on(storage::job_state()).unwrap()).unwrap();
+        assert_eq!(job.phase, JobPhase::Writing);
+        assert_eq!(job.attempts, 1);
+        assert!(!job.selected.is_empty());
+        assert!(job.written.is_empty());
+
+        // the selection is not repeated
+        let transport = sample_library();
+        let result = block_on(resume_job(
+            "auth",
+            "token",
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        assert_eq!(result, format!("Done: added {} tracks", job.selected.len()));
+        assert!(transport
+            .requests_for(constants::operations::ALBUMS_PLAYLISTS)
+            .is_empty());
+        assert!(transport
+            .requests_for(constants::operations::ALBUM_TRACKS)
+            .is_empty());
+        assert!(block_on(storage::job_state()).is_none());
+    }
+
+    #[test]
+    fn do_not_keep_jobs_that_would_fail_again() {
+        let transport = CannedTransport::default()
+            .respond_with(constants::operations::ADD_TO_PLAYLIST, &[], 404, &[], "")
+            .then(sample_library());
+
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            3,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+        assert!(result.is_ok(), "{result:?}");
+        assert!(block_on(storage::job_state()).is_none());
+
+        let transport = sample_library();
+        let result = block_on(generate_random_playlist(
+            "auth",
+            "token",
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:someone-else",
+            3,
+            &Placement::Bottom,
+            &GenerationOptions::default(),
+            &HashRegistry::default(),
+            &transport,
+        ));
+        assert!(
+            matches!(result, Err(Error::CannotEdit { .. })),
+            "{result:?}"
+        );
+        assert!(block_on(storage::job_state()).is_none());
+    }
+
+    #[test]
+    fn do_not_write_tracks_again_when_resumed() {
+        let mut job = JobState::new(
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            3,
+            Placement::Bottom,
+            GenerationOptions::default(),
+            1,
+            0,
+        );
+        job.phase = JobPhase::Writing;
+        for track_id in [
+            "7lcFWApQa0PE2Dw4mT8N1I",
+            "6IqL3kTVLsBTjmmfEpJRHT",
+            "1L7mNK7YX1qI42V5lG7kDf",
+        ] {
+            job.selected.push(SelectedTrack {
+                track_id: track_id.to_owned(),
+                source: TrackSource::LikedSongs,
+            });
+        }
+        // the first track was saved as written, the second one is in the playlist,
+        // but the job stopped before saving it
+        job.written = vec!["7lcFWApQa0PE2Dw4mT8N1I".to_owned()];
+        job.existing_uids = Some(vec!["b7ba08bda4cbbd8e".to_owned()]);
+        block_on(storage::save_job_state(
+            &serde_json::to_string(&job).unwrap(),
+        ));
+        let transport = two_item_playlist().respond(
+            constants::operations::ADD_TO_PLAYLIST,
+            include_str!("../samples/add-track.json"),
+        );
+
+        let result = block_on(resume_job(
+            "auth",
+            "token",
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        assert_eq!(result, "Done: added 3 tracks");
+        let requests = transport.requests_for(constants::operations::ADD_TO_PLAYLIST);
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].variables.contains("1L7mNK7YX1qI42V5lG7kDf"));
+        assert!(!requests[0].variables.contains("7lcFWApQa0PE2Dw4mT8N1I"));
+        assert!(!requests[0].variables.contains("6IqL3kTVLsBTjmmfEpJRHT"));
+        assert!(block_on(storage::job_state()).is_none());
+    }
+
+    #[test]
+    fn discard_jobs_that_failed_too_many_times() {
+        let mut job = JobState::new(
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            3,
+            Placement::Bottom,
+            GenerationOptions::default(),
+            1,
+            0,
+        );
+        job.attempts = constants::MAX_JOB_ATTEMPTS;
+        block_on(storage::save_job_state(
+            &serde_json::to_string(&job).unwrap(),
+        ));
+        let transport = sample_library();
+
+        let result = block_on(resume_job(
+            "auth",
+            "token",
+            &HashRegistry::default(),
+            &transport,
+        ))
+        .unwrap();
+
+        assert!(result.starts_with("Discarded"), "{result}");
+        assert!(transport.requests.borrow().is_empty());
+        assert!(block_on(storage::job_state()).is_none());
+    }
+
+    #[test]
+    fn nothing_to_resume() {
+        let transport = CannedTransport::default();
+
+        let result = block_on(resume_job(
+            "auth",
+            "token",
+            &HashRegistry::default(),
+            &transport,
+        ));
+
+        assert_eq!(result.unwrap(), "Nothing to resume");
+        assert!(transport.requests.borrow().is_empty());
+    }
 }
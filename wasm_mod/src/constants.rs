@@ -15,6 +15,52 @@ pub(crate) const MAX_TRACKS_PER_PLAYLIST: usize = 50;
 /// How many tracks should be included in the playlist we are building by default
 pub(crate) const DEFAULT_PLAYLIST_SIZE: usize = 500;
 
+/// What percentage of the new playlist can be taken from Liked Songs.
+/// Liked Songs is a single large source and would crowd out albums and playlists without a cap.
+/// The unused liked tracks go into the stash and may still be picked if albums and playlists run short.
+pub(crate) const LIKED_TRACKS_SHARE_PCT: usize = 33;
+
+/// The candidate pool is fetched this much larger than the number of tracks to add,
+/// so that picking sources at random makes a difference over taking whatever was fetched first.
+pub(crate) const POOL_OVERSAMPLING_PCT: usize = 150;
+
+/// Random seeds are kept within the range of whole numbers JS can hold exactly,
+/// so that a reported seed can be passed back from JS as a plain number.
+pub(crate) const MAX_SEED: u64 = (1 << 53) - 1;
+
+/// How many times a request is attempted before giving up on 429 and 5xx responses
+pub(crate) const MAX_RETRY_ATTEMPTS: u32 = 5;
+
+/// The first retry waits for this long, every next retry waits twice as long, plus some jitter
+pub(crate) const RETRY_BASE_DELAY_MS: u64 = 500;
+
+/// No single retry waits for longer than this. If Spotify asks for more, the request fails.
+pub(crate) const MAX_RETRY_DELAY_MS: u64 = 60_000;
+
+/// How many albums or playlists are fetched at the same time
+pub(crate) const MAX_CONCURRENT_FETCHES: usize = 4;
+
+/// Requests to Spotify are limited to this rate, no matter how many are in flight.
+/// The rate is lowered automatically if Spotify responds with 429.
+pub(crate) const MAX_REQUESTS_PER_SECOND: u32 = 10;
+
+/// How many requests can be sent at once before the rate limit kicks in
+pub(crate) const REQUEST_BURST: u32 = 5;
+
+/// Throughput is reported after this many albums or playlists are fetched
+pub(crate) const THROUGHPUT_REPORT_INTERVAL: usize = 50;
+
+/// How many tracks are added to a playlist per request.
+/// Spotify seems to accept up to 500, but a failed lot is not sent again,
+/// so smaller lots lose fewer tracks when a request fails.
+pub(crate) const ADD_ITEMS_PER_REQUEST: usize = 100;
+
+/// An unfinished job is discarded instead of resumed once it was started or resumed this many times
+pub(crate) const MAX_JOB_ATTEMPTS: u32 = 3;
+
+/// An unfinished job is discarded instead of resumed if it was started longer than this ago
+pub(crate) const MAX_JOB_AGE_MS: u64 = 24 * 60 * 60 * 1000;
+
 pub(crate) const ID_PREFIX_ALBUM: &str = "spotify:album:";
 pub(crate) const ID_PREFIX_PLAYLIST: &str = "spotify:playlist:";
 pub(crate) const ID_PREFIX_TRACK: &str = "spotify:track:";
@@ -26,6 +72,7 @@ pub(crate) mod operations {
     pub const ALBUM_TRACKS: &str = "getAlbum";
     pub const PLAYLIST_TRACKS: &str = "fetchPlaylist";
     pub const ADD_TO_PLAYLIST: &str = "addToPlaylist";
+    pub const LIKED_TRACKS: &str = "fetchLibraryTracks";
 }
 
 /// Spotify uses Apollo GraphQL with persistent queries.
@@ -40,13 +87,24 @@ pub(crate) mod persistent_queries {
         "73a3b3470804983e4d55d83cd6cc99715019228fd999d51429cc69473a18789d";
     pub const ADD_TO_PLAYLIST: &str =
         "200b7618afd05364c4aafb95e2070249ed87ee3f08fc4d2f1d5d04fdf1a516d9";
+    pub const FETCH_LIBRARY_TRACKS: &str =
+        "8474ec383b530ce3e54611fca2d8e3da57ef5612877838b8dbf00bd9fc692dfb";
 }
 
 /// Logs output into browser console. It is not the same console as for the web page because the extension runs separately.
 /// Look for the service worker console.
+#[cfg(target_arch = "wasm32")]
 macro_rules!  log {
     ( $( $t:tt )* ) => {
         web_sys::console::log_1(&format!( $( $t )* ).into())
     }
 }
+
+/// Logs output into stdout when running outside the browser, e.g. in tests.
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules!  log {
+    ( $( $t:tt )* ) => {
+        println!( $( $t )* )
+    }
+}
 pub(crate) use log;
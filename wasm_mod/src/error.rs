@@ -0,0 +1,173 @@
+//! The error type shared by the API wrappers, the client and the entry points.
+//!
+//! Every variant produced by a Spotify request carries the GraphQL operation name and the URL for logging.
+//! `Display` is the message shown to the user and should not contain any technical detail beyond that.
+
+use std::fmt::Display;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The request could not be sent or the response could not be read
+    Network {
+        operation: &'static str,
+        url: String,
+        detail: String,
+    },
+    /// Any HTTP status not covered by other variants, e.g. 404 or 5xx
+    Http {
+        operation: &'static str,
+        url: String,
+        status: u16,
+    },
+    /// 401 or 403: the session token expired or was rejected
+    Auth {
+        operation: &'static str,
+        url: String,
+        status: u16,
+    },
+    /// 429: Spotify asked to back off for `retry_after`, which is zero if there was no Retry-After header
+    RateLimited {
+        operation: &'static str,
+        url: String,
+        retry_after: Duration,
+    },
+    /// The request or the response did not match the models, e.g. Spotify changed the schema
+    Schema {
+        operation: &'static str,
+        url: String,
+        detail: String,
+    },
+    /// Spotify returned GraphQL errors instead of data
+    GraphQl {
+        operation: &'static str,
+        url: String,
+        messages: String,
+    },
+    /// Spotify no longer recognizes the persisted query hash for the operation
+    PersistedQueryNotFound {
+        operation: &'static str,
+        url: String,
+    },
+    /// The user cannot change the target playlist, e.g. it belongs to someone else and is not collaborative.
+    /// The URL is the web link to the playlist.
+    CannotEdit {
+        operation: &'static str,
+        url: String,
+        owner_uri: String,
+    },
+    /// The user cancelled the job, so the request was not sent
+    Cancelled {
+        operation: &'static str,
+        url: String,
+    },
+}
+
+impl Error {
+    /// The GraphQL operation that failed, e.g. `getAlbum`
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Error::Network { operation, .. }
+            | Error::Http { operation, .. }
+            | Error::Auth { operation, .. }
+            | Error::RateLimited { operation, .. }
+            | Error::Schema { operation, .. }
+            | Error::GraphQl { operation, .. }
+            | Error::PersistedQueryNotFound { operation, .. }
+            | Error::CannotEdit { operation, .. }
+            | Error::Cancelled { operation, .. } => operation,
+        }
+    }
+
+    /// The URL of the failed request
+    pub fn url(&self) -> &str {
+        match self {
+            Error::Network { url, .. }
+            | Error::Http { url, .. }
+            | Error::Auth { url, .. }
+            | Error::RateLimited { url, .. }
+            | Error::Schema { url, .. }
+            | Error::GraphQl { url, .. }
+            | Error::PersistedQueryNotFound { url, .. }
+            | Error::CannotEdit { url, .. }
+            | Error::Cancelled { url, .. } => url,
+        }
+    }
+
+    /// Returns how long to wait before retrying the request, or None if a retry would not help.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited { retry_after, .. } => Some(*retry_after),
+            Error::Http { status, .. } if (500..=599).contains(status) => Some(Duration::ZERO),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the same requests may succeed later, e.g. with a new session token or once Spotify is back up,
+    /// so a job that stopped with this error is worth resuming.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Network { .. } | Error::Auth { .. } | Error::RateLimited { .. } => true,
+            Error::Http { status, .. } => (500..=599).contains(status),
+            _ => false,
+        }
+    }
+
+    /// Returns true if no other request is likely to succeed after this error,
+    /// so there is no point fetching other albums or playlists.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Error::Auth { .. }
+                | Error::RateLimited { .. }
+                | Error::PersistedQueryNotFound { .. }
+                | Error::CannotEdit { .. }
+                | Error::Cancelled { .. }
+        )
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Network { operation, .. } => write!(
+                f,
+                "Cannot reach Spotify ({operation}). Check your connection and try again."
+            ),
+            Error::Http {
+                operation, status, ..
+            } => write!(f, "Spotify responded with HTTP {status} to {operation}."),
+            Error::Auth { status, .. } => write!(
+                f,
+                "Spotify rejected the session token (HTTP {status}). Reload the Spotify page and try again."
+            ),
+            Error::RateLimited { retry_after, .. } => write!(
+                f,
+                "Spotify is limiting the number of requests. Try again in {} seconds.",
+                retry_after.as_secs().max(1)
+            ),
+            Error::Schema {
+                operation, detail, ..
+            } => write!(
+                f,
+                "Unexpected response from Spotify to {operation}. It's a bug or Spotify changed its API. {detail}"
+            ),
+            Error::GraphQl {
+                operation,
+                messages,
+                ..
+            } => write!(f, "Spotify returned an error for {operation}: {messages}"),
+            Error::PersistedQueryNotFound { operation, .. } => write!(
+                f,
+                "Spotify no longer recognizes the persisted query hash for {operation}. Set a newer hash in the extension storage or wait for an update."
+            ),
+            Error::CannotEdit { .. } => write!(
+                f,
+                "Cannot add tracks to this playlist. It belongs to someone else and is not collaborative. Try again with a playlist you created or can edit."
+            ),
+            Error::Cancelled { .. } => write!(f, "Cancelled."),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
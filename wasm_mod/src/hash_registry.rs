@@ -0,0 +1,179 @@
+//! Persisted query hashes used in requests to Spotify.
+//!
+//! The hashes in `constants::persistent_queries` go stale every time Spotify changes a query.
+//! Newer hashes can be supplied at runtime without releasing a new version of the extension:
+//! * a hash manifest - a JSON file at a URL from the extension storage
+//! * overrides - a JSON object stored in the extension storage, which take priority over the manifest
+//!
+//! Both use the same format: `{"getAlbum":"46ae954ef2d2fe7732b4b2b4022157b2e18b7ea84f70591ceb164e4de1b5d5d3"}`
+
+use crate::{
+    constants::log,
+    models::operations::Operation,
+    storage,
+    transport::{HttpRequest, Transport},
+    Error, Result,
+};
+use std::collections::HashMap;
+
+/// Used in place of the GraphQL operation name in errors related to the manifest
+const HASH_MANIFEST: &str = "hashManifest";
+
+/// Returns the hash for an operation, defaulting to the compiled-in `Operation::HASH`.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct HashRegistry {
+    /// Operation name -> SHA-256 hash
+    overrides: HashMap<String, String>,
+}
+
+impl HashRegistry {
+    /// Loads the hash manifest and the overrides from the extension storage.
+    /// Any failures are logged and ignored because the compiled-in hashes may still work.
+    pub async fn load<T: Transport>(transport: &T) -> Self {
+        let mut registry = Self::default();
+
+        if let Some(url) = storage::hash_manifest_url().await {
+            match registry.load_manifest(&url, transport).await {
+                Ok(v) => log!("Loaded {v} hashes from {url}"),
+                Err(e) => log!("Cannot load hash manifest: {e:?}"),
+            }
+        }
+
+        if let Some(json) = storage::hash_overrides().await {
+            match registry.merge_json(&json) {
+                Ok(v) => log!("Loaded {v} hash overrides from storage"),
+                Err(e) => log!("Invalid hash overrides in storage: {e}"),
+            }
+        }
+
+        registry
+    }
+
+    /// Returns the persisted query hash for the operation.
+    pub fn hash<O: Operation>(&self) -> &str {
+        match self.overrides.get(O::NAME) {
+            Some(v) => v,
+            None => O::HASH,
+        }
+    }
+
+    /// Adds hashes from a JSON object with operation names as keys and hashes as values.
+    /// Existing hashes for the same operations are replaced.
+    /// Values that do not look like SHA-256 hashes are skipped.
+    /// Returns the number of hashes added.
+    pub fn merge_json(&mut self, json: &str) -> std::result::Result<usize, serde_json::Error> {
+        let hashes = serde_json::from_str::<HashMap<String, String>>(json)?;
+
+        let mut added = 0;
+        for (operation, hash) in hashes {
+            if hash.len() != 64 || !hash.chars().all(|v| v.is_ascii_hexdigit()) {
+                log!("Invalid hash for {operation}: {hash}");
+                continue;
+            }
+            self.overrides.insert(operation, hash.to_lowercase());
+            added += 1;
+        }
+
+        Ok(added)
+    }
+
+    /// Fetches the hash manifest from the URL and adds its hashes to the registry.
+    /// The request carries no Spotify credentials because the manifest may be hosted anywhere.
+    pub async fn load_manifest<T: Transport>(&mut self, url: &str, transport: &T) -> Result<usize> {
+        let resp = match transport
+            .fetch(HttpRequest {
+                method: "GET",
+                url,
+                headers: vec![("Accept", "application/json")],
+                body: None,
+            })
+            .await
+        {
+            Ok(v) => v,
+            Err(detail) => {
+                return Err(Error::Network {
+                    operation: HASH_MANIFEST,
+                    url: url.to_owned(),
+                    detail,
+                })
+            }
+        };
+
+        if !(200..300).contains(&resp.status) {
+            return Err(Error::Http {
+                operation: HASH_MANIFEST,
+                url: url.to_owned(),
+                status: resp.status,
+            });
+        }
+
+        self.merge_json(&resp.body).map_err(|e| Error::Schema {
+            operation: HASH_MANIFEST,
+            url: url.to_owned(),
+            detail: e.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::persistent_queries,
+        models::operations::{FetchPlaylist, GetAlbum, LibraryV3},
+        transport::CannedTransport,
+    };
+    use futures::executor::block_on;
+
+    const MANIFEST_URL: &str = "https://example.com/hash-manifest.json";
+    /// A made-up manifest with a new hash for one query and the compiled-in hash for another
+    const MANIFEST: &str = r#"{
+        "getAlbum": "2222222222222222222222222222222222222222222222222222222222222222",
+        "libraryV3": "17d801ba80f3a3d7405966641818c334fe32158f97e9e8b38f1a92f764345df9"
+    }"#;
+
+    #[test]
+    fn default_to_compiled_in_hashes() {
+        let registry = HashRegistry::default();
+
+        assert_eq!(registry.hash::<GetAlbum>(), persistent_queries::GET_ALBUM);
+    }
+
+    #[test]
+    fn overrides_take_priority_over_manifest() {
+        let transport = CannedTransport::default().respond(MANIFEST_URL, MANIFEST);
+        let mut registry = HashRegistry::default();
+
+        let loaded = block_on(registry.load_manifest(MANIFEST_URL, &transport)).unwrap();
+        registry
+            .merge_json(
+                r#"{"getAlbum":"1111111111111111111111111111111111111111111111111111111111111111","fetchPlaylist":"not a hash"}"#,
+            )
+            .unwrap();
+
+        assert_eq!(loaded, 2);
+        assert_eq!(
+            registry.hash::<GetAlbum>(),
+            "1111111111111111111111111111111111111111111111111111111111111111"
+        );
+        assert_eq!(
+            registry.hash::<LibraryV3>(),
+            "17d801ba80f3a3d7405966641818c334fe32158f97e9e8b38f1a92f764345df9"
+        );
+        assert_eq!(
+            registry.hash::<FetchPlaylist>(),
+            persistent_queries::FETCH_PLAYLIST
+        );
+    }
+
+    #[test]
+    fn missing_manifest_is_an_error() {
+        let transport = CannedTransport::default().respond_with(MANIFEST_URL, &[], 404, &[], "");
+        let mut registry = HashRegistry::default();
+
+        let result = block_on(registry.load_manifest(MANIFEST_URL, &transport));
+
+        assert!(matches!(result, Err(Error::Http { status: 404, .. })));
+        assert_eq!(registry.hash::<GetAlbum>(), persistent_queries::GET_ALBUM);
+    }
+}
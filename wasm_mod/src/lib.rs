@@ -1,20 +1,23 @@
 #[macro_use]
 mod client;
 mod api_wrappers;
+mod cancel;
 mod constants;
+mod error;
+mod hash_registry;
 mod models;
+mod storage;
+mod strategy;
+mod transport;
 
+use client::Placement;
 use constants::log;
+pub use error::Error;
+use hash_registry::HashRegistry;
+use models::generation_options::GenerationOptions;
+use transport::{get_runtime, BrowserRuntime, RateLimiter};
 use wasm_bindgen::prelude::*;
 
-use web_sys::{Window, WorkerGlobalScope};
-
-/// Contains the right type of the browser runtime for the current browser
-pub(crate) enum BrowserRuntime {
-    ChromeWorker(WorkerGlobalScope),
-    FireFoxWindow(Window),
-}
-
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
@@ -34,15 +37,14 @@ pub fn hello_wasm() {
     log("Hello from WASM!");
 }
 
-/// The main entry point callable from `background.js`.
-#[wasm_bindgen]
-pub async fn add_random_tracks(
-    auth_header_value: &str,
-    token_header_value: &str,
-    playlist_id: &str,
-    user_uri: &str,
+/// Sets up everything an entry point needs for a run.
+/// Returns None if something is missing. The reason is reported to the user.
+async fn prepare_run(
     number_of_tracks_to_add: Option<u32>,
-) {
+) -> Option<(RateLimiter<BrowserRuntime>, usize, HashRegistry)> {
+    // a cancellation requested before this run started is not for this run
+    cancel::reset();
+
     // try to init the browser runtime, but there is nothing we can do if it's missing
     // if it does, there is either a bug or something changed in the browser implementation
     // The runtime is a global singleton. It can probably work with OnceCell or lazy_static!.
@@ -51,10 +53,17 @@ pub async fn add_random_tracks(
         Err(e) => {
             log!("{e}");
             report_progress(e);
-            return;
+            return None;
         }
     };
 
+    // all requests in the run share the same limit no matter how many are in flight
+    let runtime = RateLimiter::new(
+        runtime,
+        constants::MAX_REQUESTS_PER_SECOND,
+        constants::REQUEST_BURST,
+    );
+
     // the number of tracks comes from a dropdown and may potentially be invalid
     let number_of_tracks_to_add: usize = match number_of_tracks_to_add {
         Some(v) => match v.try_into() {
@@ -63,12 +72,88 @@ pub async fn add_random_tracks(
                 report_progress(&format!(
                     "Cannot convert number_of_tracks_to_add. It's a bug. {number_of_tracks_to_add:?}, {e:?}"
                 ));
-                return;
+                return None;
             }
         },
         None => constants::DEFAULT_PLAYLIST_SIZE,
     };
 
+    // newer persisted query hashes may have been supplied by the user if Spotify changed some queries
+    let hashes = HashRegistry::load(&runtime).await;
+
+    Some((runtime, number_of_tracks_to_add, hashes))
+}
+
+/// Reads the options passed from JS. `undefined` or `null` mean the defaults.
+/// Returns None if the options are invalid. The reason is reported to the user.
+fn generation_options(options: JsValue) -> Option<GenerationOptions> {
+    if options.is_undefined() || options.is_null() {
+        return Some(GenerationOptions::default());
+    }
+
+    let options = match serde_wasm_bindgen::from_value::<GenerationOptions>(options) {
+        Ok(v) => v,
+        Err(e) => {
+            log!("{e:?}");
+            report_progress(&format!("Invalid options: {e}"));
+            return None;
+        }
+    };
+
+    match options.validate() {
+        Ok(()) => Some(options),
+        Err(e) => {
+            log!("{e}");
+            report_progress(&format!("Invalid options: {e}"));
+            None
+        }
+    }
+}
+
+/// The main entry point callable from `background.js`.
+/// * options - an object with the settings for selecting tracks from the popup, see `GenerationOptions` for the fields
+/// * position - where new tracks go, only `bottom` (default) is accepted for now.
+///   `top`, `interleave` and `after:<uid>` are rejected until their requests are captured.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub async fn add_random_tracks(
+    auth_header_value: &str,
+    token_header_value: &str,
+    playlist_id: &str,
+    user_uri: &str,
+    number_of_tracks_to_add: Option<u32>,
+    options: JsValue,
+    position: Option<String>,
+) {
+    // the position comes from JS and may potentially be invalid
+    let placement = match position.as_deref().unwrap_or_default().parse::<Placement>() {
+        Ok(v) if v.is_verified() => v,
+        Ok(_) => {
+            let msg = format!(
+                "Adding new tracks at \"{}\" is not supported yet, only at the bottom of the playlist",
+                position.unwrap_or_default()
+            );
+            log!("{msg}");
+            report_progress(&msg);
+            return;
+        }
+        Err(e) => {
+            log!("{e}");
+            report_progress(&e);
+            return;
+        }
+    };
+
+    let Some(options) = generation_options(options) else {
+        return;
+    };
+
+    let Some((runtime, number_of_tracks_to_add, hashes)) =
+        prepare_run(number_of_tracks_to_add).await
+    else {
+        return;
+    };
+
     // log the result for debugging and send and copy
     // of the same message to whatever frontend is listening
     // via JS sendMessage
@@ -78,6 +163,9 @@ pub async fn add_random_tracks(
         playlist_id,
         user_uri,
         number_of_tracks_to_add,
+        &placement,
+        &options,
+        &hashes,
         &runtime,
     )
     .await
@@ -87,31 +175,120 @@ pub async fn add_random_tracks(
             report_progress(&v);
         }
 
-        Err(v) => {
+        Err(e) => {
+            log!("{e:?}");
+            report_progress(&e.to_string());
+        }
+    };
+}
+
+/// Selects random tracks the same way as `add_random_tracks`, but writes nothing to the playlist.
+/// Returns an object with the selected tracks and where they came from, e.g.
+/// `{"tracks":[{"trackId":"1Gb2xAWNemgLXxufBy5qvE","source":{"type":"album","id":"7h5qFidHM4sqhcCHSbiMzL"}}],"duplicatesSkipped":0,"failedSources":0}`
+/// or `undefined` if the selection failed. Errors are reported as progress messages.
+/// * options - the same settings for selecting tracks as in `add_random_tracks`
+#[wasm_bindgen]
+pub async fn preview_random_tracks(
+    auth_header_value: &str,
+    token_header_value: &str,
+    playlist_id: &str,
+    user_uri: &str,
+    number_of_tracks_to_add: Option<u32>,
+    options: JsValue,
+) -> JsValue {
+    let Some(options) = generation_options(options) else {
+        return JsValue::UNDEFINED;
+    };
+
+    let Some((runtime, number_of_tracks_to_add, hashes)) =
+        prepare_run(number_of_tracks_to_add).await
+    else {
+        return JsValue::UNDEFINED;
+    };
+
+    let selection = match client::select_tracks(
+        auth_header_value,
+        token_header_value,
+        playlist_id,
+        user_uri,
+        number_of_tracks_to_add,
+        &options,
+        &hashes,
+        &runtime,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log!("{e:?}");
+            report_progress(&e.to_string());
+            return JsValue::UNDEFINED;
+        }
+    };
+
+    report_progress(&format!(
+        "Preview: selected {} tracks with seed {}",
+        selection.tracks.len(),
+        selection.seed
+    ));
+
+    match serde_wasm_bindgen::to_value(&selection) {
+        Ok(v) => v,
+        Err(e) => {
+            log!("{e:?}");
+            report_progress(&format!("Cannot return the selection. It's a bug. {e}"));
+            JsValue::UNDEFINED
+        }
+    }
+}
+
+/// Picks up the job that stopped before it finished, e.g. because the browser terminated the service worker.
+/// Tracks written before the job stopped are not added again. The result is reported as progress messages.
+#[wasm_bindgen]
+pub async fn resume_job(auth_header_value: &str, token_header_value: &str) {
+    let Some((runtime, _, hashes)) = prepare_run(None).await else {
+        return;
+    };
+
+    match client::resume_job(auth_header_value, token_header_value, &hashes, &runtime).await {
+        Ok(v) => {
             log!("{v}");
             report_progress(&v);
         }
+
+        Err(e) => {
+            log!("{e:?}");
+            report_progress(&e.to_string());
+        }
     };
 }
 
+/// Stops the running job before its next request to Spotify.
+/// The job reports what it has already written. Does nothing if there is no job running.
+#[wasm_bindgen]
+pub fn cancel_current_job() {
+    log!("Cancellation requested");
+    report_progress("Cancelling...");
+    cancel::cancel();
+}
+
 /// This is a proxy for report_progress() in progress.js
 /// to send messages to other js scripts.
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(module = "/src/progress.js")]
 extern "C" {
     pub fn report_progress(msg: &str);
 }
 
-/// All error handling in this crate is based on either retrying a request after some time
-/// or exiting gracefully.
-#[derive(Debug, Clone)]
-pub enum RetryAfter {
-    Seconds(i64),
-    Never,
+/// There is no one to send progress messages to outside the browser, so they go to stdout.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn report_progress(msg: &str) {
+    println!("Progress: {msg}");
 }
 
 /// The result type that should be used in place of std::Result
 /// throughout the app
-pub type Result<T> = std::result::Result<T, RetryAfter>;
+pub type Result<T> = std::result::Result<T, Error>;
 
 #[allow(dead_code)]
 pub fn set_panic_hook() {
@@ -124,50 +301,3 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
-
-// pub async fn sleep(duration: Duration) {
-//     JsFuture::from(Promise::new(&mut |yes, _| {
-//         window()
-//             .unwrap()
-//             .set_timeout_with_callback_and_timeout_and_arguments_0(
-//                 &yes,
-//                 duration.as_millis() as i32,
-//             )
-//             .unwrap();
-//     }))
-//     .await
-//     .unwrap();
-// }
-
-/// Returns the right type of runtime for the current browser because
-/// Firefox and Chrome do not agree on the parent object for Runtime in WebWorkers.
-/// Firefox uses Window and Chrome uses WorkerGlobalScope.
-async fn get_runtime() -> std::result::Result<BrowserRuntime, &'static str> {
-    // try for chrome first and return if found
-    // it should also work if FF switches to using WorkerGlobalScope as they should
-    match js_sys::global().dyn_into::<WorkerGlobalScope>() {
-        Ok(v) => {
-            return Ok(BrowserRuntime::ChromeWorker(v));
-        }
-        Err(e) => {
-            log!("ServiceWorkerGlobalScope unavailable");
-            log!("{:?}", e);
-        }
-    };
-
-    // this is a fallback for Firefox, but it does not make sense why they would use Window in
-    // web workers
-    match web_sys::window() {
-        Some(v) => {
-            return Ok(BrowserRuntime::FireFoxWindow(v));
-        }
-        None => {
-            log!("Window unavailable");
-        }
-    };
-
-    // no runtime was found, which is a serious problem
-    // because all fetch calls require it
-    // TODO: may be worth a retry
-    Err("Missing browser runtime. It's a bug.")
-}
@@ -1,9 +1,19 @@
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::{constants, hash_registry::HashRegistry};
+use operations::Operation;
+
 pub(crate) mod album;
 pub(crate) mod albums_playlists;
+pub(crate) mod generation_options;
+pub(crate) mod graphql;
+pub(crate) mod job_state;
+pub(crate) mod liked_tracks;
+pub(crate) mod operations;
+pub(crate) mod pagination;
 pub(crate) mod playlist;
+pub(crate) mod selection;
 
 /// Use this response type to discard the response payload
 #[derive(Debug, Deserialize, Serialize)]
@@ -27,7 +37,7 @@ impl Default for VariablesV3Items {
         Self {
             filters: Vec::new(),
             order: "Recently Added".to_owned(),
-            limit: 50,
+            limit: constants::ITEMS_PER_PAGE,
             offset: 0,
             text_filter: String::new(),
         }
@@ -49,7 +59,7 @@ pub(crate) struct VariablesAlbumOrPlaylistTracks {
 impl Default for VariablesAlbumOrPlaylistTracks {
     fn default() -> Self {
         Self {
-            limit: 50,
+            limit: constants::ITEMS_PER_PAGE,
             offset: 0,
             uri: String::new(),
             locale: String::new(),
@@ -58,7 +68,26 @@ impl Default for VariablesAlbumOrPlaylistTracks {
 }
 
 /// A serializable struct for adding variables to a spotify request
-/// ````
+///
+/// {"offset":0,"limit":50}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VariablesLikedTracks {
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl Default for VariablesLikedTracks {
+    fn default() -> Self {
+        Self {
+            limit: constants::ITEMS_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+/// A serializable struct for adding variables to a spotify request
+/// ```text
 ///     "variables": {
 ///         "uris": [
 ///         "spotify:track:7lcFWApQa0PE2Dw4mT8N1I",
@@ -92,22 +121,53 @@ pub(crate) struct NewPosition {
     from_uid: Option<String>,
 }
 
+/// Where `addToPlaylist` puts the new tracks.
+/// Only `BOTTOM_OF_PLAYLIST` was captured in `samples/`. `TOP_OF_PLAYLIST` and `AFTER_UID` are not verified,
+/// so `add_random_tracks` does not accept the placements that need them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) enum InsertPosition {
+    #[default]
+    Bottom,
+    Top,
+    /// Right after the playlist item with this UID
+    AfterUid(String),
+}
+
+impl InsertPosition {
+    /// Every lot sent to the top or after a UID goes in front of the lots sent there before,
+    /// so the lots have to be sent last to first to keep the tracks in order.
+    pub(crate) fn reverses_lots(&self) -> bool {
+        !matches!(self, Self::Bottom)
+    }
+}
+
+impl From<&InsertPosition> for NewPosition {
+    fn from(position: &InsertPosition) -> Self {
+        let (move_type, from_uid) = match position {
+            InsertPosition::Bottom => ("BOTTOM_OF_PLAYLIST", None),
+            InsertPosition::Top => ("TOP_OF_PLAYLIST", None),
+            InsertPosition::AfterUid(uid) => ("AFTER_UID", Some(uid.clone())),
+        };
+        Self {
+            move_type: move_type.to_owned(),
+            from_uid,
+        }
+    }
+}
+
 impl Default for VariablesAddTracksToPlaylist {
     fn default() -> Self {
         Self {
             uris: Vec::new(),
             playlist_uri: String::new(),
-            new_position: NewPosition {
-                from_uid: None,
-                move_type: "BOTTOM_OF_PLAYLIST".to_owned(),
-            },
+            new_position: NewPosition::from(&InsertPosition::Bottom),
         }
     }
 }
 
 /// The top level structure for including variables as POST payload to a spotify request.  
 /// Get requests have the top level members as HTTP query params and should be serialized individually.
-/// ````
+/// ```text
 /// {
 ///     "variables": {
 ///         ... <T> ...
@@ -144,6 +204,22 @@ pub(crate) struct PersistedQuery {
     pub sha256_hash: String,
 }
 
+impl<T: Serialize> Payload<T> {
+    /// Creates the payload with the name and the persisted query hash of the operation.
+    pub fn new<O: Operation<Variables = T>>(variables: T, hashes: &HashRegistry) -> Self {
+        Self {
+            variables,
+            operation_name: O::NAME.to_owned(),
+            extensions: PayloadExtensions {
+                persisted_query: PersistedQuery {
+                    sha256_hash: hashes.hash::<O>().to_owned(),
+                    ..Default::default()
+                },
+            },
+        }
+    }
+}
+
 impl Default for PersistedQuery {
     fn default() -> Self {
         Self {
@@ -0,0 +1,278 @@
+// Settings for selecting tracks, passed from the popup as a JS object.
+// Every field is optional and defaults to the values in `constants.rs`, so `{}` or `undefined` keep the old behavior.
+// Unknown fields are rejected to catch typos in the JS code, e.g.
+// {"tracksPerAlbum":2,"includeLikedSongs":false,"excludeSources":["7h5qFidHM4sqhcCHSbiMzL"],"maxAlbums":200,"sourcePolicy":"proportional"}
+// The options are saved with the job state, so a resumed job selects tracks the same way.
+
+use super::selection::SourceKind;
+use crate::constants;
+use serde::{Deserialize, Serialize};
+
+/// The order Spotify lists albums and playlists in.
+/// It matters only if the number of albums or playlists is limited, otherwise they are shuffled anyway.
+/// The web player offers other orders, e.g. `Alphabetical`, but only `Recently Added` was captured in `samples/`,
+/// so the others are not accepted until a request with them is captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum LibraryOrder {
+    #[default]
+    RecentlyAdded,
+}
+
+impl LibraryOrder {
+    /// The value of `order` in libraryV3 requests
+    pub fn as_variable(&self) -> &'static str {
+        match self {
+            Self::RecentlyAdded => "Recently Added",
+        }
+    }
+}
+
+/// How the sources share the new tracks between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SourcePolicy {
+    /// Every album, playlist and Liked Songs has the same odds of giving the next track
+    #[default]
+    Equal,
+    /// Larger sources have better odds, up to `source_weight_cap` tracks
+    Proportional,
+    /// Sources give their quota in the order they were fetched in until there are enough tracks
+    Sequential,
+}
+
+/// What percentage of the new tracks should come from every kind of source, e.g.
+/// `{"likedSongsPct":50,"albumsPct":30,"playlistsPct":20}`. The percentages add up to 100.
+/// The share of a kind that runs short goes to the other kinds in proportion to their shares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct SourceMix {
+    pub liked_songs_pct: usize,
+    pub albums_pct: usize,
+    pub playlists_pct: usize,
+}
+
+impl SourceMix {
+    /// Percentages in the order of `SourceKind::ALL`
+    pub fn shares_pct(&self) -> [usize; 3] {
+        [self.liked_songs_pct, self.albums_pct, self.playlists_pct]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct GenerationOptions {
+    /// How many tracks are selected from every album
+    pub tracks_per_album: usize,
+    /// How many tracks are selected from every playlist
+    pub tracks_per_playlist: usize,
+    /// How many tracks are fetched from every playlist to select from
+    pub max_tracks_per_playlist: usize,
+    /// What percentage of the new tracks can come from Liked Songs
+    pub liked_songs_share_pct: usize,
+    pub include_albums: bool,
+    pub include_playlists: bool,
+    pub include_liked_songs: bool,
+    /// Skip playlists owned by other users, e.g. followed or collaborative ones
+    pub own_playlists_only: bool,
+    /// Album and playlist IDs without the `spotify:album:` or `spotify:playlist:` prefix to never select from
+    pub exclude_sources: Vec<String>,
+    pub library_order: LibraryOrder,
+    /// Only the first N albums in `library_order` are used if set
+    pub max_albums: Option<usize>,
+    /// Only the first N playlists in `library_order` are used if set
+    pub max_playlists: Option<usize>,
+    pub source_policy: SourcePolicy,
+    /// Sources with more tracks than this have the same odds in `SourcePolicy::Proportional`
+    pub source_weight_cap: usize,
+    /// Splits the new tracks between liked songs, albums and playlists if set.
+    /// It replaces `liked_songs_share_pct`, `source_policy` applies within every kind.
+    pub source_mix: Option<SourceMix>,
+    /// The same seed selects the same tracks from the same library.
+    /// A random seed is used if not set. It is reported at the end of the run to be able to repeat it.
+    pub seed: Option<u64>,
+}
+
+impl Default for GenerationOptions {
+    fn default() -> Self {
+        Self {
+            tracks_per_album: constants::MIN_TRACKS_PER_ALBUM,
+            tracks_per_playlist: constants::MIN_TRACKS_PER_ALBUM,
+            max_tracks_per_playlist: constants::MAX_TRACKS_PER_PLAYLIST,
+            liked_songs_share_pct: constants::LIKED_TRACKS_SHARE_PCT,
+            include_albums: true,
+            include_playlists: true,
+            include_liked_songs: true,
+            own_playlists_only: false,
+            exclude_sources: Vec::new(),
+            library_order: LibraryOrder::default(),
+            max_albums: None,
+            max_playlists: None,
+            source_policy: SourcePolicy::default(),
+            source_weight_cap: constants::MAX_TRACKS_PER_PLAYLIST,
+            source_mix: None,
+            seed: None,
+        }
+    }
+}
+
+impl GenerationOptions {
+    /// Checks the values that cannot be rejected by deserialization alone.
+    /// Returns a message for the user naming the option that is wrong.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.include_albums && !self.include_playlists && !self.include_liked_songs {
+            return Err(
+                "Nothing to select from: enable at least one of includeAlbums, includePlaylists or includeLikedSongs"
+                    .to_owned(),
+            );
+        }
+        if let Some(mix) = &self.source_mix {
+            let total = mix.shares_pct().iter().sum::<usize>();
+            if total != 100 {
+                return Err(format!(
+                    "sourceMix percentages must add up to 100, got {total}"
+                ));
+            }
+            for kind in SourceKind::ALL {
+                if mix.shares_pct()[kind.index()] > 0 && !self.includes(kind) {
+                    return Err(format!(
+                        "sourceMix asks for {kind}, but they are turned off"
+                    ));
+                }
+            }
+        }
+        if self.tracks_per_album == 0 {
+            return Err("tracksPerAlbum must be at least 1".to_owned());
+        }
+        if self.tracks_per_playlist == 0 {
+            return Err("tracksPerPlaylist must be at least 1".to_owned());
+        }
+        if self.tracks_per_playlist > self.max_tracks_per_playlist {
+            return Err(format!(
+                "tracksPerPlaylist ({}) cannot be more than maxTracksPerPlaylist ({})",
+                self.tracks_per_playlist, self.max_tracks_per_playlist
+            ));
+        }
+        if self.liked_songs_share_pct > 100 {
+            return Err(format!(
+                "likedSongsSharePct must be between 0 and 100, got {}",
+                self.liked_songs_share_pct
+            ));
+        }
+        if self.max_albums == Some(0) {
+            return Err(
+                "maxAlbums must be at least 1. Set includeAlbums to false to skip albums."
+                    .to_owned(),
+            );
+        }
+        if self.max_playlists == Some(0) {
+            return Err(
+                "maxPlaylists must be at least 1. Set includePlaylists to false to skip playlists."
+                    .to_owned(),
+            );
+        }
+        if self.source_weight_cap == 0 {
+            return Err("sourceWeightCap must be at least 1".to_owned());
+        }
+        if let Some(v) = self.seed.filter(|v| *v > constants::MAX_SEED) {
+            return Err(format!(
+                "seed must be between 0 and {}, got {v}",
+                constants::MAX_SEED
+            ));
+        }
+        if let Some(v) = self.exclude_sources.iter().find(|v| v.contains(':')) {
+            return Err(format!(
+                "excludeSources should contain IDs without the spotify:album: or spotify:playlist: prefix, got {v}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the kind of sources is selected from
+    pub fn includes(&self, kind: SourceKind) -> bool {
+        match kind {
+            SourceKind::LikedSongs => self.include_liked_songs,
+            SourceKind::Albums => self.include_albums,
+            SourceKind::Playlists => self.include_playlists,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_validate_options() {
+        let options = serde_json::from_str::<GenerationOptions>(
+            r#"{"tracksPerAlbum":2,"includeLikedSongs":false,"libraryOrder":"recentlyAdded","maxAlbums":200,"sourcePolicy":"proportional"}"#,
+        )
+        .unwrap();
+        assert_eq!(options.tracks_per_album, 2);
+        assert_eq!(options.tracks_per_playlist, constants::MIN_TRACKS_PER_ALBUM);
+        assert!(!options.include_liked_songs);
+        assert_eq!(options.library_order.as_variable(), "Recently Added");
+        assert_eq!(options.max_albums, Some(200));
+        assert_eq!(options.source_policy, SourcePolicy::Proportional);
+        assert!(options.validate().is_ok());
+
+        assert_eq!(
+            serde_json::from_str::<GenerationOptions>("{}").unwrap(),
+            GenerationOptions::default()
+        );
+        assert!(serde_json::from_str::<GenerationOptions>(r#"{"tracksPerAlbums":2}"#).is_err());
+        // only the order in the samples is accepted
+        assert!(
+            serde_json::from_str::<GenerationOptions>(r#"{"libraryOrder":"alphabetical"}"#)
+                .is_err()
+        );
+
+        let options = GenerationOptions {
+            tracks_per_playlist: 60,
+            ..Default::default()
+        };
+        assert_eq!(
+            options.validate().unwrap_err(),
+            "tracksPerPlaylist (60) cannot be more than maxTracksPerPlaylist (50)"
+        );
+        let options = GenerationOptions {
+            include_albums: false,
+            include_playlists: false,
+            include_liked_songs: false,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+
+        let options = serde_json::from_str::<GenerationOptions>(
+            r#"{"sourceMix":{"likedSongsPct":50,"albumsPct":30,"playlistsPct":20}}"#,
+        )
+        .unwrap();
+        assert!(options.validate().is_ok());
+        let options = GenerationOptions {
+            source_mix: Some(SourceMix {
+                liked_songs_pct: 50,
+                albums_pct: 30,
+                playlists_pct: 10,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.validate().unwrap_err(),
+            "sourceMix percentages must add up to 100, got 90"
+        );
+        let options = GenerationOptions {
+            include_liked_songs: false,
+            source_mix: Some(SourceMix {
+                liked_songs_pct: 50,
+                albums_pct: 50,
+                playlists_pct: 0,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.validate().unwrap_err(),
+            "sourceMix asks for liked songs, but they are turned off"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+// errors -> [message, extensions -> code]
+// data is null when there are errors
+//
+// {"errors":[{"message":"PersistedQueryNotFound","extensions":{"code":"PERSISTED_QUERY_NOT_FOUND"}}],"data":null}
+
+use serde::{de::IgnoredAny, Deserialize};
+use std::fmt::Display;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Extensions {
+    /// E.g. `PERSISTED_QUERY_NOT_FOUND`
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GraphQlError {
+    pub message: String,
+    #[serde(default)]
+    pub extensions: Extensions,
+}
+
+impl GraphQlError {
+    /// Returns true if Spotify did not recognize the persisted query hash.
+    pub fn is_persisted_query_not_found(&self) -> bool {
+        self.message == "PersistedQueryNotFound"
+            || self.extensions.code.as_deref() == Some("PERSISTED_QUERY_NOT_FOUND")
+    }
+}
+
+impl Display for GraphQlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.extensions.code {
+            Some(code) => write!(f, "{} ({code})", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The root node of any GraphQL response with only the parts needed to tell if it failed.
+/// A response with both `data` and `errors` is a partial success and is not treated as an error.
+#[derive(Debug, Deserialize)]
+pub struct GraphQlErrorsRoot {
+    #[serde(default)]
+    pub errors: Vec<GraphQlError>,
+    /// Null or missing if the request failed
+    pub data: Option<IgnoredAny>,
+}
+
+impl GraphQlErrorsRoot {
+    /// Returns true if Spotify returned errors instead of data.
+    pub fn is_error(&self) -> bool {
+        !self.errors.is_empty() && self.data.is_none()
+    }
+}
@@ -0,0 +1,165 @@
+// The state of a job that writes to a playlist, kept in the extension storage to resume the job.
+// Chrome terminates the MV3 service worker after a few minutes without events, which may happen in the middle of a long run.
+// The state is saved after every phase and every write batch, so a resumed job repeats at most one phase or one batch.
+// Fetched tracks are kept in the candidate pool until the selection is made, so the pool may be large.
+// It is stored as JSON, so the field names are camelCase for consistency with the JS side.
+
+use super::{
+    generation_options::GenerationOptions,
+    selection::{SelectedTrack, TrackSource},
+};
+use crate::{api_wrappers::PlaylistItem, client::Placement, constants, strategy::Candidates};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What the job has done so far, in the order of the phases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum JobPhase {
+    /// Nothing has been fetched yet
+    #[default]
+    Started,
+    /// Albums and playlists are known and liked songs are in the pool
+    SourcesFound,
+    /// The selection is complete and the tracks are being written to the playlist
+    Writing,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JobState {
+    /// Playlist ID without the `spotify:playlist:` prefix
+    pub target_playlist_id: String,
+    pub user_uri: String,
+    pub number_of_tracks_to_add: usize,
+    pub placement: Placement,
+    pub options: GenerationOptions,
+    pub phase: JobPhase,
+    /// When the job was started, in ms as per `Transport::now_ms`
+    pub started_at: u64,
+    /// How many times the job was started or resumed
+    pub attempts: u32,
+    /// Albums and playlists mixed together in the random order they are fetched in
+    pub sources: Vec<TrackSource>,
+    /// Tracks fetched from every source, cleared once the selection is made
+    pub pool: Vec<Candidates>,
+    /// Tracks in the order they are written to the playlist, empty until the selection is made
+    pub selected: Vec<SelectedTrack>,
+    /// Tracks not selected because they are already in the target playlist
+    pub duplicates: HashSet<String>,
+    /// The number of albums, playlists and liked songs that could not be fetched
+    pub failed_sources: usize,
+    /// UIDs of the playlist items that were there before the job started writing, None until then
+    pub existing_uids: Option<Vec<String>>,
+    /// Track IDs already written to the playlist
+    pub written: Vec<String>,
+    /// The seed the random sequence started from, reported to the user to repeat the run
+    pub seed: u64,
+    /// Saved with the rest of the state for the resumed job to continue the same random sequence
+    pub rng: ChaCha8Rng,
+    /// Jobs that write nothing, e.g. previews, are not saved
+    #[serde(skip)]
+    pub resumable: bool,
+    /// Items of the target playlist fetched for the selection and reused for writing.
+    /// They are not saved, so a resumed job fetches them again.
+    #[serde(skip)]
+    pub target_items: Option<Vec<PlaylistItem>>,
+}
+
+impl JobState {
+    pub fn new(
+        target_playlist_id: &str,
+        user_uri: &str,
+        number_of_tracks_to_add: usize,
+        placement: Placement,
+        options: GenerationOptions,
+        seed: u64,
+        started_at: u64,
+    ) -> Self {
+        Self {
+            target_playlist_id: target_playlist_id.to_owned(),
+            user_uri: user_uri.to_owned(),
+            number_of_tracks_to_add,
+            placement,
+            options,
+            phase: JobPhase::Started,
+            started_at,
+            attempts: 0,
+            sources: Vec::new(),
+            pool: Vec::new(),
+            selected: Vec::new(),
+            duplicates: HashSet::new(),
+            failed_sources: 0,
+            existing_uids: None,
+            written: Vec::new(),
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            resumable: false,
+            target_items: None,
+        }
+    }
+
+    /// Returns true if the job should be discarded instead of resumed.
+    /// An old job may add tracks the user no longer expects and a job that keeps failing is not going to finish.
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        self.attempts >= constants::MAX_JOB_ATTEMPTS
+            || now_ms.saturating_sub(self.started_at) > constants::MAX_JOB_AGE_MS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn continue_the_same_random_sequence_after_reload() {
+        let mut job = JobState::new(
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            10,
+            Placement::AfterUid("b7ba08bda4cbbd8e".to_owned()),
+            GenerationOptions::default(),
+            7,
+            1_000,
+        );
+        job.rng.gen::<u64>();
+        job.selected.push(SelectedTrack {
+            track_id: "7lcFWApQa0PE2Dw4mT8N1I".to_owned(),
+            source: TrackSource::Album {
+                id: "4aawyAB9vmqN3uQ7FjRGTy".to_owned(),
+            },
+        });
+
+        let mut loaded =
+            serde_json::from_str::<JobState>(&serde_json::to_string(&job).unwrap()).unwrap();
+
+        assert_eq!(loaded.placement, job.placement);
+        assert_eq!(loaded.selected, job.selected);
+        assert_eq!(loaded.seed, 7);
+        assert_eq!(loaded.started_at, 1_000);
+        assert!(!loaded.resumable);
+        assert_eq!(loaded.rng.gen::<u64>(), job.rng.gen::<u64>());
+    }
+
+    #[test]
+    fn expire_old_jobs_and_jobs_that_keep_failing() {
+        let mut job = JobState::new(
+            "3h9rkMXa434AeAIDdA5Dd2",
+            "spotify:user:onebro.me",
+            10,
+            Placement::Bottom,
+            GenerationOptions::default(),
+            7,
+            1_000,
+        );
+        job.attempts = 1;
+        assert!(!job.is_expired(1_000 + constants::MAX_JOB_AGE_MS));
+        assert!(job.is_expired(1_001 + constants::MAX_JOB_AGE_MS));
+
+        job.attempts = constants::MAX_JOB_ATTEMPTS;
+        assert!(job.is_expired(1_000));
+    }
+}
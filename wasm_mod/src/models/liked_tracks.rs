@@ -0,0 +1,55 @@
+// data -> me -> library -> tracks -> items -> [track -> _uri]
+// ... tracks -> totalCount
+// ... track -> data -> playability -> playable
+
+use serde::Deserialize;
+#[derive(Debug, Deserialize, Default)]
+pub struct Playability {
+    pub playable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrackData {
+    #[serde(default)]
+    pub playability: Playability,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Track {
+    /// E.g. `spotify:track:0VDl9zuwZANYlvY5XmYDc5`
+    #[serde(rename = "_uri")]
+    pub uri: String,
+    pub data: TrackData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Items {
+    pub track: Track,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tracks {
+    pub items: Vec<Items>,
+    pub total_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Library {
+    pub tracks: Tracks,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Me {
+    pub library: Library,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RootData {
+    pub me: Me,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LikedTracksRoot {
+    pub data: RootData,
+}
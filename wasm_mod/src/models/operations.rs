@@ -0,0 +1,74 @@
+// Every pathfinder operation is a pair of a persisted query and its variables.
+// Spotify responds with a structure specific to the query.
+// The trait ties them together so that the wrong variables or response type for an operation
+// is a compile error rather than a deserialization error at runtime.
+
+use super::{
+    album, albums_playlists, liked_tracks, playlist, IgnoredData, VariablesAddTracksToPlaylist,
+    VariablesAlbumOrPlaylistTracks, VariablesLikedTracks, VariablesV3Items,
+};
+use crate::constants::{operations, persistent_queries};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// A GraphQL operation with a persisted query
+pub(crate) trait Operation {
+    /// The value for `operationName`
+    const NAME: &'static str;
+    /// The SHA-256 hash of the persisted query
+    const HASH: &'static str;
+    /// Goes into `variables` of the query string or the POST payload
+    type Variables: Serialize + Debug;
+    /// The response with `data` on success
+    type Response: for<'de> Deserialize<'de>;
+}
+
+/// Tracks of an album
+pub(crate) struct GetAlbum;
+
+impl Operation for GetAlbum {
+    const NAME: &'static str = operations::ALBUM_TRACKS;
+    const HASH: &'static str = persistent_queries::GET_ALBUM;
+    type Variables = VariablesAlbumOrPlaylistTracks;
+    type Response = album::AlbumTracksRoot;
+}
+
+/// Albums or playlists saved in the user library
+pub(crate) struct LibraryV3;
+
+impl Operation for LibraryV3 {
+    const NAME: &'static str = operations::ALBUMS_PLAYLISTS;
+    const HASH: &'static str = persistent_queries::LIBRARY_V3;
+    type Variables = VariablesV3Items;
+    type Response = albums_playlists::LibV3ItemsRoot;
+}
+
+/// Tracks and the owner of a playlist
+pub(crate) struct FetchPlaylist;
+
+impl Operation for FetchPlaylist {
+    const NAME: &'static str = operations::PLAYLIST_TRACKS;
+    const HASH: &'static str = persistent_queries::FETCH_PLAYLIST;
+    type Variables = VariablesAlbumOrPlaylistTracks;
+    type Response = playlist::PlaylistRoot;
+}
+
+/// Liked Songs
+pub(crate) struct FetchLibraryTracks;
+
+impl Operation for FetchLibraryTracks {
+    const NAME: &'static str = operations::LIKED_TRACKS;
+    const HASH: &'static str = persistent_queries::FETCH_LIBRARY_TRACKS;
+    type Variables = VariablesLikedTracks;
+    type Response = liked_tracks::LikedTracksRoot;
+}
+
+/// Adds tracks to a playlist via POST. The response payload is ignored.
+pub(crate) struct AddToPlaylist;
+
+impl Operation for AddToPlaylist {
+    const NAME: &'static str = operations::ADD_TO_PLAYLIST;
+    const HASH: &'static str = persistent_queries::ADD_TO_PLAYLIST;
+    type Variables = VariablesAddTracksToPlaylist;
+    type Response = IgnoredData;
+}
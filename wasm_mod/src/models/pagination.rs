@@ -0,0 +1,101 @@
+// Pathfinder queries that return lists take `offset` and `limit` variables
+// and return the page of items with the total number of items in the list.
+
+use super::{
+    album, albums_playlists, liked_tracks, playlist, VariablesAlbumOrPlaylistTracks,
+    VariablesLikedTracks, VariablesV3Items,
+};
+
+/// Variables of a query that returns one page of a list at a time
+pub(crate) trait PaginatedVariables {
+    fn offset(&self) -> usize;
+    fn set_offset(&mut self, offset: usize);
+    /// The maximum number of items per page
+    fn limit(&self) -> usize;
+}
+
+/// A response with one page of a list
+pub(crate) trait Page {
+    type Item;
+
+    /// The number of items in the entire list, not just this page
+    fn total_count(&self) -> usize;
+
+    /// Moves the items out of the page and leaves the rest of the response in place
+    fn take_items(&mut self) -> Vec<Self::Item>;
+}
+
+/// Implements `PaginatedVariables` for structs with `offset` and `limit` fields
+macro_rules! impl_paginated_variables {
+    ($($t:ty),+) => {
+        $(
+            impl PaginatedVariables for $t {
+                fn offset(&self) -> usize {
+                    self.offset
+                }
+
+                fn set_offset(&mut self, offset: usize) {
+                    self.offset = offset;
+                }
+
+                fn limit(&self) -> usize {
+                    self.limit
+                }
+            }
+        )+
+    };
+}
+
+impl_paginated_variables!(
+    VariablesV3Items,
+    VariablesAlbumOrPlaylistTracks,
+    VariablesLikedTracks
+);
+
+impl Page for album::AlbumTracksRoot {
+    type Item = album::Items;
+
+    fn total_count(&self) -> usize {
+        self.data.album_union.tracks.total_count
+    }
+
+    fn take_items(&mut self) -> Vec<Self::Item> {
+        std::mem::take(&mut self.data.album_union.tracks.items)
+    }
+}
+
+impl Page for playlist::PlaylistRoot {
+    type Item = playlist::Item;
+
+    fn total_count(&self) -> usize {
+        self.data.playlist_v2.content.total_count
+    }
+
+    fn take_items(&mut self) -> Vec<Self::Item> {
+        std::mem::take(&mut self.data.playlist_v2.content.items)
+    }
+}
+
+impl Page for albums_playlists::LibV3ItemsRoot {
+    type Item = albums_playlists::Items;
+
+    fn total_count(&self) -> usize {
+        self.data.me.library_v3.total_count
+    }
+
+    fn take_items(&mut self) -> Vec<Self::Item> {
+        std::mem::take(&mut self.data.me.library_v3.items)
+    }
+}
+
+impl Page for liked_tracks::LikedTracksRoot {
+    type Item = liked_tracks::Items;
+
+    fn total_count(&self) -> usize {
+        self.data.me.library.tracks.total_count
+    }
+
+    fn take_items(&mut self) -> Vec<Self::Item> {
+        std::mem::take(&mut self.data.me.library.tracks.items)
+    }
+}
@@ -4,6 +4,9 @@ use serde::Deserialize;
 // ... content -> totalCount
 // ... itemV2 -> data -> playability -> playable
 // ... playlistV2 -> ownerV2 -> data -> uri
+// ... playlistV2 -> collaborative
+// ... playlistV2 -> currentUserCapabilities -> canEditItems
+// ... items -> [uid]
 
 // Playlist ownership -------------------------------------
 #[derive(Debug, Deserialize)]
@@ -19,6 +22,16 @@ pub struct OwnerV2 {
     pub data: OwnerV2Data,
 }
 
+/// What the user who sent the request can do with the playlist.
+/// Not present in the captured samples, so it is only a fallback when the user is neither the owner nor a collaborator.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrentUserCapabilities {
+    /// Adding, removing and moving items
+    #[serde(default)]
+    pub can_edit_items: bool,
+}
+
 // list of tracks ------------------------------------------
 #[derive(Debug, Deserialize, Default)]
 pub struct Playability {
@@ -41,6 +54,10 @@ pub struct ItemV2 {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Item {
+    /// Identifies this particular entry in the playlist, e.g. `b7ba08bda4cbbd8e`.
+    /// The same track added twice has two different UIDs.
+    #[serde(default)]
+    pub uid: String,
     pub item_v2: ItemV2,
 }
 
@@ -55,6 +72,10 @@ pub struct Content {
 #[serde(rename_all = "camelCase")]
 pub struct PlaylistV2 {
     pub owner_v2: OwnerV2,
+    /// Anyone with a link can add to a collaborative playlist, not just the owner
+    #[serde(default)]
+    pub collaborative: bool,
+    pub current_user_capabilities: Option<CurrentUserCapabilities>,
     pub content: Content,
 }
 
@@ -0,0 +1,78 @@
+// The result of selecting tracks for the target playlist before anything is written to it.
+// It is returned to JS as is by the preview entry point, so the field names are camelCase.
+// Selected tracks and their sources are also saved with the job state.
+
+use serde::{Deserialize, Serialize};
+
+/// Where a selected track came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum TrackSource {
+    LikedSongs,
+    /// Album ID without the `spotify:album:` prefix
+    Album {
+        id: String,
+    },
+    /// Playlist ID without the `spotify:playlist:` prefix
+    Playlist {
+        id: String,
+    },
+}
+
+impl TrackSource {
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            Self::LikedSongs => SourceKind::LikedSongs,
+            Self::Album { .. } => SourceKind::Albums,
+            Self::Playlist { .. } => SourceKind::Playlists,
+        }
+    }
+}
+
+/// Sources of the same kind share the same part of the source mix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SourceKind {
+    LikedSongs,
+    Albums,
+    Playlists,
+}
+
+impl SourceKind {
+    pub const ALL: [SourceKind; 3] = [Self::LikedSongs, Self::Albums, Self::Playlists];
+
+    /// The position of the kind in `ALL` and in arrays of per-kind values
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl std::fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LikedSongs => write!(f, "liked songs"),
+            Self::Albums => write!(f, "albums"),
+            Self::Playlists => write!(f, "playlists"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SelectedTrack {
+    /// Track ID without the `spotify:track:` prefix
+    pub track_id: String,
+    pub source: TrackSource,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Selection {
+    /// Tracks to be added to the target playlist in random order
+    pub tracks: Vec<SelectedTrack>,
+    /// The number of tracks not selected because they are already in the target playlist
+    pub duplicates_skipped: usize,
+    /// The number of albums, playlists and liked songs that could not be fetched
+    pub failed_sources: usize,
+    /// The same seed and options select the same tracks from the same library
+    pub seed: u64,
+}
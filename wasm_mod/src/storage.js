@@ -0,0 +1,64 @@
+// Access to extension storage for WASM.
+// The settings are optional and are meant to be set by hand when Spotify changes its persisted queries
+// before a new version of the extension is released, e.g. from the background script console:
+//
+// chrome.storage.local.set({ hashOverrides: { getAlbum: "46ae954ef2d2fe7732b4b2b4022157b2e18b7ea84f70591ceb164e4de1b5d5d3" } })
+// chrome.storage.local.set({ hashManifestUrl: "https://example.com/hash-manifest.json" })
+//
+// jobState is written by WASM while a job is running to resume it if the service worker is terminated.
+//
+// These functions are imported into storage.rs
+
+// Returns a JSON object with operation names and hashes as a string, e.g. `{"getAlbum":"46ae..."}`.
+// Returns an empty string if there are no overrides.
+export async function get_hash_overrides() {
+  // storage errors should not bring down the WASM
+  try {
+    const values = await chrome.storage.local.get("hashOverrides");
+    return values?.hashOverrides ? JSON.stringify(values.hashOverrides) : "";
+  }
+  catch (e) {
+    console.error(`Cannot read hashOverrides: ${e}`);
+    return "";
+  }
+}
+
+// Returns the URL of the hash manifest or an empty string if there is none.
+export async function get_hash_manifest_url() {
+  try {
+    const values = await chrome.storage.local.get("hashManifestUrl");
+    return values?.hashManifestUrl ?? "";
+  }
+  catch (e) {
+    console.error(`Cannot read hashManifestUrl: ${e}`);
+    return "";
+  }
+}
+
+// Returns the JSON state of a job that did not finish or an empty string if there is none.
+export async function get_job_state() {
+  try {
+    const values = await chrome.storage.local.get("jobState");
+    return values?.jobState ?? "";
+  }
+  catch (e) {
+    console.error(`Cannot read jobState: ${e}`);
+    return "";
+  }
+}
+
+// Saves the JSON state of the running job as a string.
+// An empty string removes it.
+export async function set_job_state(json) {
+  try {
+    if (json) {
+      await chrome.storage.local.set({ jobState: json });
+    }
+    else {
+      await chrome.storage.local.remove("jobState");
+    }
+  }
+  catch (e) {
+    console.error(`Cannot save jobState: ${e}`);
+  }
+}
@@ -0,0 +1,72 @@
+//! Settings stored in the extension storage by the user and the state of the running job, see `storage.js` for details.
+//! Settings are None in tests and native builds. The job state is kept in memory there.
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// These are proxies for the functions in storage.js
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(module = "/src/storage.js")]
+extern "C" {
+    async fn get_hash_overrides() -> JsValue;
+    async fn get_hash_manifest_url() -> JsValue;
+    async fn get_job_state() -> JsValue;
+    async fn set_job_state(json: &str);
+}
+
+/// Returns a JSON object with operation names and persisted query hashes, if any.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn hash_overrides() -> Option<String> {
+    get_hash_overrides()
+        .await
+        .as_string()
+        .filter(|v| !v.is_empty())
+}
+
+/// Returns the URL of a JSON file with operation names and persisted query hashes, if any.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn hash_manifest_url() -> Option<String> {
+    get_hash_manifest_url()
+        .await
+        .as_string()
+        .filter(|v| !v.is_empty())
+}
+
+/// Returns the JSON state of a job that did not finish, if any.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn job_state() -> Option<String> {
+    get_job_state().await.as_string().filter(|v| !v.is_empty())
+}
+
+/// Saves the JSON state of the running job. An empty string removes it.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn save_job_state(json: &str) {
+    set_job_state(json).await
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    /// Stands in for the extension storage in tests, one per test thread
+    static JOB_STATE: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn hash_overrides() -> Option<String> {
+    None
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn hash_manifest_url() -> Option<String> {
+    None
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn job_state() -> Option<String> {
+    JOB_STATE.with_borrow(|v| v.clone())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn save_job_state(json: &str) {
+    let json = Some(json.to_owned()).filter(|v| !v.is_empty());
+    JOB_STATE.set(json);
+}
@@ -0,0 +1,734 @@
+//! Picks tracks for the target playlist out of everything fetched from the user library.
+//!
+//! `client` fetches Liked Songs, albums and playlists and collects their tracks into a candidate pool,
+//! asking the strategy after every source if there is enough to stop fetching.
+//! The strategy then selects the tracks out of the pool without any network I/O,
+//! so strategies can be swapped and tested with a hand-made pool.
+//!
+//! Albums and playlists are fetched in one random order, so the pool is a fair sample of the whole library
+//! no matter how many albums or playlists there are.
+
+use crate::{
+    constants,
+    models::{
+        generation_options::{GenerationOptions, SourceMix, SourcePolicy},
+        selection::{SelectedTrack, SourceKind, TrackSource},
+    },
+};
+use rand::{seq::SliceRandom, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Tracks of a single album, playlist or Liked Songs that can be selected.
+/// Tracks already in the target playlist are not included.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Candidates {
+    pub source: TrackSource,
+    /// Track IDs in the order Spotify returned them
+    pub tracks: Vec<String>,
+}
+
+/// Selects tracks out of the candidate pool.
+/// The pool lists the sources in the order they were fetched in, which is random.
+pub(crate) trait SelectionStrategy {
+    /// Returns true if the pool has enough candidates to stop fetching more sources.
+    fn has_enough(&self, pool: &[&Candidates], number_of_tracks: usize) -> bool;
+
+    /// Returns unique tracks in the order they should be added to the target playlist.
+    fn select(
+        &self,
+        pool: &[&Candidates],
+        number_of_tracks: usize,
+        rng: &mut dyn RngCore,
+    ) -> Vec<SelectedTrack>;
+}
+
+/// Returns the strategy for the source policy and the source mix chosen in the options.
+pub(crate) fn for_options(options: &GenerationOptions) -> Box<dyn SelectionStrategy> {
+    if let Some(mix) = options.source_mix {
+        return Box::new(MixOfKinds::new(options, mix));
+    }
+
+    match options.source_policy {
+        SourcePolicy::Sequential => Box::new(SampleAndStash::new(options)),
+        SourcePolicy::Equal | SourcePolicy::Proportional => Box::new(FairPool::new(options)),
+    }
+}
+
+/// Takes a few random tracks from every source in the order the sources were fetched in
+/// until there are enough, then makes up for any shortage with random tracks left over from all sources.
+/// The last source is not cut short, so there may be a few more tracks than asked for.
+pub(crate) struct SampleAndStash {
+    pub tracks_per_album: usize,
+    pub tracks_per_playlist: usize,
+    /// Liked Songs is a single large source and would crowd out albums and playlists without a cap
+    pub liked_songs_share_pct: usize,
+}
+
+impl SampleAndStash {
+    pub fn new(options: &GenerationOptions) -> Self {
+        Self {
+            tracks_per_album: options.tracks_per_album,
+            tracks_per_playlist: options.tracks_per_playlist,
+            liked_songs_share_pct: options.liked_songs_share_pct,
+        }
+    }
+
+    /// How many tracks are selected from the source before the leftovers are used
+    fn quota(&self, source: &TrackSource, number_of_tracks: usize) -> usize {
+        match source {
+            TrackSource::LikedSongs => number_of_tracks * self.liked_songs_share_pct / 100,
+            TrackSource::Album { .. } => self.tracks_per_album,
+            TrackSource::Playlist { .. } => self.tracks_per_playlist,
+        }
+    }
+
+    /// How many tracks can be selected from the pool without using the leftovers
+    fn capacity(&self, pool: &[&Candidates], number_of_tracks: usize) -> usize {
+        // tracks found in more than one source are counted more than once,
+        // which may stop fetching a bit early, but the leftovers make up for it
+        pool.iter()
+            .map(|v| v.tracks.len().min(self.quota(&v.source, number_of_tracks)))
+            .sum::<usize>()
+    }
+}
+
+/// Adds random leftovers to the selection until there are enough tracks.
+fn fill_from_leftovers(
+    selected: &mut Vec<SelectedTrack>,
+    selected_ids: &mut HashSet<String>,
+    mut leftovers: Vec<SelectedTrack>,
+    number_of_tracks: usize,
+    rng: &mut dyn RngCore,
+) {
+    if selected.len() >= number_of_tracks {
+        return;
+    }
+
+    leftovers.shuffle(rng);
+    for track in leftovers {
+        if selected.len() >= number_of_tracks {
+            break;
+        }
+        if selected_ids.insert(track.track_id.clone()) {
+            selected.push(track);
+        }
+    }
+}
+
+impl SelectionStrategy for SampleAndStash {
+    fn has_enough(&self, pool: &[&Candidates], number_of_tracks: usize) -> bool {
+        self.capacity(pool, number_of_tracks) >= number_of_tracks
+    }
+
+    fn select(
+        &self,
+        pool: &[&Candidates],
+        number_of_tracks: usize,
+        rng: &mut dyn RngCore,
+    ) -> Vec<SelectedTrack> {
+        let mut selected: Vec<SelectedTrack> = Vec::new();
+        let mut selected_ids: HashSet<String> = HashSet::new();
+        let mut stashed: Vec<SelectedTrack> = Vec::new();
+
+        for candidates in pool {
+            let mut tracks = candidates.tracks.clone();
+            tracks.shuffle(rng);
+
+            // sources after the ones that provided enough tracks only go into the stash
+            let quota = if selected.len() < number_of_tracks {
+                self.quota(&candidates.source, number_of_tracks)
+                    .min(tracks.len())
+            } else {
+                0
+            };
+            let leftovers = tracks.split_off(quota);
+
+            // tracks that are in more than one source keep the source they were selected from first
+            for track_id in tracks {
+                if selected_ids.insert(track_id.clone()) {
+                    selected.push(SelectedTrack {
+                        track_id,
+                        source: candidates.source.clone(),
+                    });
+                }
+            }
+            stashed.extend(leftovers.into_iter().map(|track_id| SelectedTrack {
+                track_id,
+                source: candidates.source.clone(),
+            }));
+        }
+
+        // add tracks from the stash if the selected list is not long enough
+        fill_from_leftovers(
+            &mut selected,
+            &mut selected_ids,
+            stashed,
+            number_of_tracks,
+            rng,
+        );
+
+        // tracks from the same source should not end up next to each other
+        selected.shuffle(rng);
+
+        selected
+    }
+}
+
+/// Picks tracks one at a time from a random source, so every source has a chance no matter where it is in the pool.
+/// Sources are picked with the same odds or with the odds proportional to their size up to a cap,
+/// and no source gives more than its quota until all sources are used up.
+/// The pool is fetched larger than needed for the odds to make a difference.
+pub(crate) struct FairPool {
+    quotas: SampleAndStash,
+    policy: SourcePolicy,
+    /// Sources larger than this have the same odds as a source of this size in `SourcePolicy::Proportional`
+    weight_cap: usize,
+}
+
+impl FairPool {
+    pub fn new(options: &GenerationOptions) -> Self {
+        Self {
+            quotas: SampleAndStash::new(options),
+            policy: options.source_policy,
+            weight_cap: options.source_weight_cap,
+        }
+    }
+
+    /// The odds of the source being picked relative to other sources
+    fn weight(&self, candidates: &Candidates) -> usize {
+        match self.policy {
+            SourcePolicy::Proportional => candidates.tracks.len().min(self.weight_cap),
+            SourcePolicy::Equal | SourcePolicy::Sequential => 1,
+        }
+    }
+}
+
+impl SelectionStrategy for FairPool {
+    fn has_enough(&self, pool: &[&Candidates], number_of_tracks: usize) -> bool {
+        self.quotas.capacity(pool, number_of_tracks)
+            >= number_of_tracks * constants::POOL_OVERSAMPLING_PCT / 100
+    }
+
+    fn select(
+        &self,
+        pool: &[&Candidates],
+        number_of_tracks: usize,
+        rng: &mut dyn RngCore,
+    ) -> Vec<SelectedTrack> {
+        let mut selected: Vec<SelectedTrack> = Vec::new();
+        let mut selected_ids: HashSet<String> = HashSet::new();
+
+        // every source is shuffled once and its tracks are taken one by one
+        let mut sources = pool
+            .iter()
+            .map(|v| {
+                let mut tracks = v.tracks.clone();
+                tracks.shuffle(rng);
+                let quota = self.quotas.quota(&v.source, number_of_tracks);
+                (v, tracks, quota)
+            })
+            .collect::<Vec<_>>();
+        // sources drop out of the draw once their quota is used up, but their other tracks are still leftovers
+        let mut open = (0..sources.len())
+            .filter(|i| {
+                let (v, tracks, quota) = &sources[*i];
+                *quota > 0 && !tracks.is_empty() && self.weight(v) > 0
+            })
+            .collect::<Vec<usize>>();
+
+        while selected.len() < number_of_tracks && !open.is_empty() {
+            let total_weight = open
+                .iter()
+                .map(|i| self.weight(sources[*i].0))
+                .sum::<usize>();
+            let mut pick = rng.gen_range(0..total_weight);
+            let position = open
+                .iter()
+                .position(|i| {
+                    let weight = self.weight(sources[*i].0);
+                    if pick < weight {
+                        true
+                    } else {
+                        pick -= weight;
+                        false
+                    }
+                })
+                .unwrap_or_default();
+
+            let (candidates, tracks, quota) = &mut sources[open[position]];
+            if let Some(track_id) = tracks.pop() {
+                // a track in more than one source is only taken once and does not count towards the quota
+                if selected_ids.insert(track_id.clone()) {
+                    *quota -= 1;
+                    selected.push(SelectedTrack {
+                        track_id,
+                        source: candidates.source.clone(),
+                    });
+                }
+            }
+            if *quota == 0 || tracks.is_empty() {
+                open.swap_remove(position);
+            }
+        }
+
+        // whatever was not picked makes up for a shortage
+        let leftovers = sources
+            .into_iter()
+            .flat_map(|(v, tracks, _)| {
+                tracks.into_iter().map(|track_id| SelectedTrack {
+                    track_id,
+                    source: v.source.clone(),
+                })
+            })
+            .collect::<Vec<SelectedTrack>>();
+        fill_from_leftovers(
+            &mut selected,
+            &mut selected_ids,
+            leftovers,
+            number_of_tracks,
+            rng,
+        );
+
+        // tracks are picked in random order already, but the leftovers are added at the end
+        selected.shuffle(rng);
+
+        selected
+    }
+}
+
+/// Splits `total` into parts proportional to `weights` that add up to `total`.
+/// The parts that are rounded down the most get the remainder.
+fn split_by_weights(total: usize, weights: [usize; 3]) -> [usize; 3] {
+    let total_weight = weights.iter().sum::<usize>();
+    if total_weight == 0 {
+        return [0; 3];
+    }
+
+    let mut parts = weights.map(|v| total * v / total_weight);
+    let mut remainder = total - parts.iter().sum::<usize>();
+    let mut by_rounding = (0..weights.len())
+        .filter(|i| weights[*i] > 0)
+        .collect::<Vec<usize>>();
+    by_rounding.sort_by_key(|i| std::cmp::Reverse(total * weights[*i] % total_weight));
+    for i in by_rounding.into_iter().cycle() {
+        if remainder == 0 {
+            break;
+        }
+        parts[i] += 1;
+        remainder -= 1;
+    }
+
+    parts
+}
+
+/// How many tracks every kind of source should give for the mix,
+/// with the share of the kinds that run short given to the other kinds in proportion to their shares.
+/// `available` is how many tracks every kind has, in the order of `SourceKind::ALL`.
+pub(crate) fn mix_targets(
+    mix: &SourceMix,
+    number_of_tracks: usize,
+    available: [usize; 3],
+) -> [usize; 3] {
+    let shares = mix.shares_pct();
+    let mut targets = split_by_weights(number_of_tracks, shares);
+
+    loop {
+        let mut shortfall = 0;
+        for (target, available) in targets.iter_mut().zip(available) {
+            if *target > available {
+                shortfall += *target - available;
+                *target = available;
+            }
+        }
+        if shortfall == 0 {
+            break;
+        }
+
+        // the kinds that can give more share the shortfall, even if they were not asked for anything
+        let open = (0..targets.len())
+            .map(|i| targets[i] < available[i])
+            .collect::<Vec<bool>>();
+        let mut weights = [0; 3];
+        for i in 0..weights.len() {
+            if open[i] {
+                weights[i] = shares[i];
+            }
+        }
+        if weights.iter().all(|v| *v == 0) {
+            for i in 0..weights.len() {
+                if open[i] {
+                    weights[i] = 1;
+                }
+            }
+        }
+        if weights.iter().all(|v| *v == 0) {
+            break;
+        }
+
+        for (target, extra) in targets.iter_mut().zip(split_by_weights(shortfall, weights)) {
+            *target += extra;
+        }
+    }
+
+    targets
+}
+
+/// Gives every kind of source its share of the new tracks and leaves it to another strategy
+/// to pick the tracks within the kind.
+pub(crate) struct MixOfKinds {
+    mix: SourceMix,
+    /// Kinds turned off in the options have nothing to give
+    included: [bool; 3],
+    within_kind: Box<dyn SelectionStrategy>,
+}
+
+impl MixOfKinds {
+    pub fn new(options: &GenerationOptions, mix: SourceMix) -> Self {
+        // liked songs are a kind of their own, so their whole share can come from them
+        let within_kind = GenerationOptions {
+            liked_songs_share_pct: 100,
+            source_mix: None,
+            ..options.clone()
+        };
+
+        Self {
+            mix,
+            included: SourceKind::ALL.map(|v| options.includes(v)),
+            within_kind: for_options(&within_kind),
+        }
+    }
+
+    /// The part of the pool with the sources of the same kind
+    fn of_kind<'a>(pool: &[&'a Candidates], kind: SourceKind) -> Vec<&'a Candidates> {
+        pool.iter()
+            .filter(|v| v.source.kind() == kind)
+            .copied()
+            .collect()
+    }
+}
+
+impl SelectionStrategy for MixOfKinds {
+    fn has_enough(&self, pool: &[&Candidates], number_of_tracks: usize) -> bool {
+        // liked songs are in the pool before any albums or playlists are fetched,
+        // so only their number is known for sure
+        let available = SourceKind::ALL.map(|kind| match kind {
+            _ if !self.included[kind.index()] => 0,
+            SourceKind::LikedSongs => Self::of_kind(pool, kind)
+                .iter()
+                .map(|v| v.tracks.len())
+                .sum(),
+            SourceKind::Albums | SourceKind::Playlists => usize::MAX,
+        });
+        let targets = mix_targets(&self.mix, number_of_tracks, available);
+
+        SourceKind::ALL
+            .into_iter()
+            .filter(|v| *v != SourceKind::LikedSongs && targets[v.index()] > 0)
+            .all(|v| {
+                self.within_kind
+                    .has_enough(&Self::of_kind(pool, v), targets[v.index()])
+            })
+    }
+
+    fn select(
+        &self,
+        pool: &[&Candidates],
+        number_of_tracks: usize,
+        rng: &mut dyn RngCore,
+    ) -> Vec<SelectedTrack> {
+        let mut selected: Vec<SelectedTrack> = Vec::new();
+        let mut selected_ids: HashSet<String> = HashSet::new();
+
+        let available = SourceKind::ALL.map(|kind| {
+            Self::of_kind(pool, kind)
+                .iter()
+                .flat_map(|v| v.tracks.iter())
+                .collect::<HashSet<&String>>()
+                .len()
+        });
+        let targets = mix_targets(&self.mix, number_of_tracks, available);
+
+        for kind in SourceKind::ALL {
+            // a track in sources of different kinds counts towards the kind that selected it first
+            // only the sources with such tracks are copied to leave them out
+            let of_kind = Self::of_kind(pool, kind);
+            let deduped = of_kind
+                .iter()
+                .map(|v| {
+                    v.tracks
+                        .iter()
+                        .any(|v| selected_ids.contains(v))
+                        .then(|| Candidates {
+                            source: v.source.clone(),
+                            tracks: v
+                                .tracks
+                                .iter()
+                                .filter(|v| !selected_ids.contains(*v))
+                                .cloned()
+                                .collect(),
+                        })
+                })
+                .collect::<Vec<Option<Candidates>>>();
+            let candidates = of_kind
+                .iter()
+                .zip(deduped.iter())
+                .map(|(v, deduped)| deduped.as_ref().unwrap_or(v))
+                .collect::<Vec<&Candidates>>();
+
+            // the strategy within the kind may select a few more than asked for
+            let target = targets[kind.index()];
+            for track in self
+                .within_kind
+                .select(&candidates, target, rng)
+                .into_iter()
+                .take(target)
+            {
+                if selected_ids.insert(track.track_id.clone()) {
+                    selected.push(track);
+                }
+            }
+        }
+
+        // tracks shared between kinds may leave the selection a bit short
+        let leftovers = pool
+            .iter()
+            .flat_map(|v| {
+                v.tracks.iter().map(|track_id| SelectedTrack {
+                    track_id: track_id.clone(),
+                    source: v.source.clone(),
+                })
+            })
+            .collect::<Vec<SelectedTrack>>();
+        fill_from_leftovers(
+            &mut selected,
+            &mut selected_ids,
+            leftovers,
+            number_of_tracks,
+            rng,
+        );
+
+        selected.shuffle(rng);
+
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn candidates(source: TrackSource, prefix: &str, n: usize) -> Candidates {
+        Candidates {
+            source,
+            tracks: (0..n).map(|v| format!("{prefix}{v}")).collect(),
+        }
+    }
+
+    /// The strategies borrow the sources like `client` does
+    fn refs(pool: &[Candidates]) -> Vec<&Candidates> {
+        pool.iter().collect()
+    }
+
+    fn album(id: &str, n: usize) -> Candidates {
+        candidates(TrackSource::Album { id: id.to_owned() }, id, n)
+    }
+
+    fn strategy() -> SampleAndStash {
+        SampleAndStash {
+            tracks_per_album: 3,
+            tracks_per_playlist: 3,
+            liked_songs_share_pct: 20,
+        }
+    }
+
+    fn count_from(selected: &[SelectedTrack], source: &TrackSource) -> usize {
+        selected.iter().filter(|v| &v.source == source).count()
+    }
+
+    #[test]
+    fn sample_sources_in_the_order_they_were_fetched() {
+        let pool = vec![
+            candidates(TrackSource::LikedSongs, "liked", 100),
+            album("a", 10),
+            album("b", 2),
+            album("c", 10),
+            album("d", 10),
+        ];
+
+        let selected = strategy().select(&refs(&pool), 10, &mut StdRng::seed_from_u64(1));
+
+        // 2 liked songs are 20% of 10, then 3 + 2 + 3 tracks from albums and the last album is not needed
+        assert_eq!(selected.len(), 10);
+        assert_eq!(count_from(&selected, &TrackSource::LikedSongs), 2);
+        assert_eq!(count_from(&selected, &pool[2].source), 2);
+        assert_eq!(count_from(&selected, &pool[4].source), 0);
+    }
+
+    #[test]
+    fn make_up_for_a_shortage_from_leftovers() {
+        let pool = vec![
+            candidates(TrackSource::LikedSongs, "liked", 100),
+            album("a", 10),
+            // the same tracks as in album a are not selected twice
+            album("a", 10),
+        ];
+
+        let selected = strategy().select(&refs(&pool), 20, &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(selected.len(), 20);
+        let unique = selected
+            .iter()
+            .map(|v| v.track_id.as_str())
+            .collect::<HashSet<&str>>();
+        assert_eq!(unique.len(), 20);
+    }
+
+    #[test]
+    fn stop_fetching_once_quotas_can_be_filled() {
+        let pool = vec![
+            candidates(TrackSource::LikedSongs, "liked", 100),
+            album("a", 10),
+            album("b", 2),
+        ];
+
+        // 20% of 10 liked songs + 3 + 2 album tracks are not enough for 10, but 1 + 3 + 2 are enough for 6
+        assert!(!strategy().has_enough(&refs(&pool), 10));
+        assert!(strategy().has_enough(&refs(&pool), 6));
+    }
+
+    fn fair_pool(policy: SourcePolicy) -> FairPool {
+        FairPool {
+            quotas: strategy(),
+            policy,
+            weight_cap: 20,
+        }
+    }
+
+    #[test]
+    fn give_every_source_a_chance_no_matter_the_order() {
+        // 20 albums would fill the playlist under SampleAndStash before the playlists get a chance
+        let mut pool = (0..20)
+            .map(|v| album(&format!("a{v}-"), 10))
+            .collect::<Vec<Candidates>>();
+        pool.extend((0..20).map(|v| {
+            candidates(
+                TrackSource::Playlist {
+                    id: format!("p{v}"),
+                },
+                &format!("p{v}-"),
+                10,
+            )
+        }));
+
+        let selected =
+            fair_pool(SourcePolicy::Equal).select(&refs(&pool), 30, &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(selected.len(), 30);
+        let from_playlists = selected
+            .iter()
+            .filter(|v| matches!(v.source, TrackSource::Playlist { .. }))
+            .count();
+        assert!(from_playlists > 5, "{from_playlists}");
+        // no source gives more than its quota
+        assert!(pool.iter().all(|v| count_from(&selected, &v.source) <= 3));
+    }
+
+    #[test]
+    fn favour_larger_sources_up_to_the_cap() {
+        let pool = vec![album("small", 1), album("large", 100), album("huge", 1000)];
+        let fair_pool = FairPool {
+            quotas: SampleAndStash {
+                tracks_per_album: 1000,
+                tracks_per_playlist: 1000,
+                liked_songs_share_pct: 0,
+            },
+            ..fair_pool(SourcePolicy::Proportional)
+        };
+
+        let selected = fair_pool.select(&refs(&pool), 40, &mut StdRng::seed_from_u64(1));
+
+        // large and huge sources have the same odds because of the cap of 20
+        assert_eq!(selected.len(), 40);
+        assert!(count_from(&selected, &pool[0].source) <= 1);
+        assert!(count_from(&selected, &pool[1].source) > 10);
+        assert!(count_from(&selected, &pool[2].source) > 10);
+    }
+
+    #[test]
+    fn fetch_more_than_needed_for_a_fair_pool() {
+        let pool = vec![album("a", 10), album("b", 10), album("c", 10)];
+
+        // 9 tracks fit into the quotas, which is enough for 9 tracks without a choice or for 6 with a choice
+        assert!(strategy().has_enough(&refs(&pool), 9));
+        assert!(!fair_pool(SourcePolicy::Equal).has_enough(&refs(&pool), 7));
+        assert!(fair_pool(SourcePolicy::Equal).has_enough(&refs(&pool), 6));
+        assert_eq!(
+            fair_pool(SourcePolicy::Equal)
+                .select(&refs(&pool), 6, &mut StdRng::seed_from_u64(1))
+                .len(),
+            6
+        );
+    }
+
+    #[test]
+    fn give_the_share_of_a_kind_that_runs_short_to_the_others() {
+        let mix = SourceMix {
+            liked_songs_pct: 50,
+            albums_pct: 30,
+            playlists_pct: 20,
+        };
+
+        assert_eq!(mix_targets(&mix, 100, [1000, 1000, 1000]), [50, 30, 20]);
+        assert_eq!(mix_targets(&mix, 7, [1000, 1000, 1000]), [4, 2, 1]);
+        // 40 liked songs short go to albums and playlists as 3:2
+        assert_eq!(mix_targets(&mix, 100, [10, 1000, 1000]), [10, 54, 36]);
+        // the shortfall of albums then goes to playlists
+        assert_eq!(mix_targets(&mix, 100, [10, 40, 1000]), [10, 40, 50]);
+        // a kind asked for nothing makes up for the others if nothing else can
+        let mix = SourceMix {
+            liked_songs_pct: 0,
+            albums_pct: 100,
+            playlists_pct: 0,
+        };
+        assert_eq!(mix_targets(&mix, 60, [30, 20, 30]), [20, 20, 20]);
+        assert_eq!(mix_targets(&mix, 100, [10, 5, 10]), [10, 5, 10]);
+    }
+
+    #[test]
+    fn select_tracks_in_the_mix() {
+        let mut pool = vec![candidates(TrackSource::LikedSongs, "liked", 100)];
+        pool.extend((0..10).map(|v| album(&format!("a{v}-"), 10)));
+        pool.push(candidates(
+            TrackSource::Playlist { id: "p".to_owned() },
+            "p",
+            4,
+        ));
+        let options = GenerationOptions {
+            source_mix: Some(SourceMix {
+                liked_songs_pct: 50,
+                albums_pct: 30,
+                playlists_pct: 20,
+            }),
+            ..Default::default()
+        };
+        let strategy = for_options(&options);
+
+        let selected = strategy.select(&refs(&pool), 40, &mut StdRng::seed_from_u64(1));
+
+        // the playlist has 4 of 8 tracks asked for, the other 4 are split between liked songs and albums 5:3 as 3 and 1
+        let count = |kind: SourceKind| selected.iter().filter(|v| v.source.kind() == kind).count();
+        assert_eq!(selected.len(), 40);
+        assert_eq!(count(SourceKind::Playlists), 4);
+        assert_eq!(count(SourceKind::LikedSongs), 23);
+        assert_eq!(count(SourceKind::Albums), 13);
+
+        // liked songs are all there is to know before the albums and playlists are fetched
+        assert!(!strategy.has_enough(&refs(&pool[..1]), 40));
+        // 2 tracks out of the playlist are few enough to pick from 3
+        assert!(strategy.has_enough(&refs(&pool), 10));
+        assert!(!strategy.has_enough(&refs(&pool), 20));
+    }
+}
@@ -0,0 +1,56 @@
+//! HTTP plumbing between the API wrappers and whatever can actually send the requests.
+//!
+//! The browser `fetch` is only available inside the extension, so the API wrappers and the client
+//! are generic over `Transport` to run the same code in `cargo test` or any other native environment.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+mod browser;
+#[cfg(test)]
+mod canned;
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+mod rate_limiter;
+
+pub(crate) use browser::{get_runtime, BrowserRuntime};
+#[cfg(test)]
+pub(crate) use canned::CannedTransport;
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(unused_imports)]
+pub(crate) use native::NativeTransport;
+pub(crate) use rate_limiter::RateLimiter;
+
+/// Everything a transport needs to send a request to Spotify.
+#[derive(Debug)]
+pub(crate) struct HttpRequest<'a> {
+    /// GET or POST
+    pub method: &'static str,
+    pub url: &'a str,
+    pub headers: Vec<(&'static str, &'a str)>,
+    /// Serialized JSON payload for POST requests
+    pub body: Option<String>,
+}
+
+/// The parts of an HTTP response the API wrappers care about.
+#[derive(Debug)]
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    /// Header names are always lower-case
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+/// Sends HTTP requests on behalf of the API wrappers.
+pub(crate) trait Transport {
+    /// Sends the request and reads the response body to completion.
+    /// Only failures to get any response at all are errors, described in plain text.
+    /// HTTP error statuses are returned as a normal response for the caller to inspect.
+    async fn fetch(&self, request: HttpRequest<'_>) -> Result<HttpResponse, String>;
+
+    /// Waits for the specified duration without blocking other requests, where possible.
+    async fn sleep(&self, duration: Duration);
+
+    /// Returns the current time in milliseconds for measuring intervals.
+    fn now_ms(&self) -> u64;
+}
@@ -0,0 +1,194 @@
+use super::{HttpRequest, HttpResponse, Transport};
+use crate::constants::log;
+use std::collections::HashMap;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Request, RequestInit, RequestMode, Response, Window, WorkerGlobalScope};
+
+/// Contains the right type of the browser runtime for the current browser
+pub(crate) enum BrowserRuntime {
+    ChromeWorker(WorkerGlobalScope),
+    FireFoxWindow(Window),
+}
+
+impl Transport for BrowserRuntime {
+    /// Sends the request via the browser `fetch` API.
+    async fn fetch(&self, request: HttpRequest<'_>) -> Result<HttpResponse, String> {
+        // set request params
+        let opts = RequestInit::new();
+        opts.set_mode(RequestMode::Cors);
+        opts.set_method(request.method);
+        if let Some(body) = &request.body {
+            opts.set_body(&JsValue::from_str(body));
+        }
+
+        // create the request
+        let url = request.url;
+        let js_request = match Request::new_with_str_and_init(url, &opts) {
+            Ok(v) => v,
+            Err(e) => {
+                log!("Spotify request creation failed");
+                log!("{:?}", e);
+                // TODO: may be worth a retry
+                return Err(format!("{:?}", e));
+            }
+        };
+
+        // add headers
+        for (name, value) in request.headers {
+            let _ = js_request.headers().set(name, value);
+        }
+
+        // both window and globalscope have the same interface, but they are separate types so Rust has
+        // to have separate paths for them
+        // the output is the same type for both
+        let resp_value = match self {
+            BrowserRuntime::ChromeWorker(v) => {
+                JsFuture::from(v.fetch_with_request(&js_request)).await
+            }
+            BrowserRuntime::FireFoxWindow(v) => {
+                JsFuture::from(v.fetch_with_request(&js_request)).await
+            }
+        };
+
+        // unwrap the response
+        let resp_value = match resp_value {
+            Ok(v) => v,
+            Err(e) => {
+                log!("Spotify request failed");
+                log!("{url}");
+                log!("{:?}", e);
+                // TODO: may be worth a retry
+                return Err(format!("{:?}", e));
+            }
+        };
+
+        // exit if the response is not of the expected type
+        if !resp_value.is_instance_of::<Response>() {
+            log!("Spotify response in not Response");
+            log!("{url}");
+            log!("{:?}", resp_value);
+            // TODO: may be worth a retry
+            return Err("Spotify response in not Response".to_owned());
+        };
+
+        // this is unlikely to fail because of the previous type check
+        let resp: Response = match resp_value.dyn_into() {
+            Ok(v) => v,
+            Err(e) => {
+                log!("Cannot typecast response to Response");
+                log!("{url}");
+                log!("{:?}", e);
+                // TODO: may be worth a retry
+                return Err(format!("{:?}", e));
+            }
+        };
+
+        // Headers is iterable in JS and every entry is a [name, value] array
+        let mut headers = HashMap::new();
+        if let Ok(Some(entries)) = js_sys::try_iter(&resp.headers()) {
+            for entry in entries.flatten() {
+                let entry = js_sys::Array::from(&entry);
+                if let (Some(name), Some(value)) =
+                    (entry.get(0).as_string(), entry.get(1).as_string())
+                {
+                    headers.insert(name.to_lowercase(), value);
+                }
+            }
+        }
+
+        // Read the response stream to completion.
+        // In theory, the stream may still be open and the op may take some time to complete
+        let body = match resp.text() {
+            Ok(v) => JsFuture::from(v).await,
+            Err(e) => {
+                log!("Cannot convert Promise to Future");
+                log!("{url}");
+                log!("{:?}", e);
+                // TODO: may be worth a retry
+                return Err(format!("{:?}", e));
+            }
+        };
+
+        // Unwrap the response and handle the error
+        let body = match body {
+            Ok(v) => v.as_string().unwrap_or_default(),
+            Err(e) => {
+                log!("Spotify request failed");
+                log!("{url}");
+                log!("{:?}", e);
+                // TODO: may be worth a retry
+                return Err(format!("{:?}", e));
+            }
+        };
+
+        Ok(HttpResponse {
+            status: resp.status(),
+            headers,
+            body,
+        })
+    }
+
+    /// Resolves a JS Promise via `setTimeout` to let other futures run while waiting.
+    async fn sleep(&self, duration: Duration) {
+        let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            let result = match self {
+                BrowserRuntime::ChromeWorker(v) => {
+                    v.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+                }
+                BrowserRuntime::FireFoxWindow(v) => {
+                    v.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+                }
+            };
+            if let Err(e) = result {
+                log!("setTimeout failed");
+                log!("{:?}", e);
+                // resolve right away rather than hang forever
+                let _ = resolve.call0(&JsValue::NULL);
+            }
+        });
+
+        let _ = JsFuture::from(promise).await;
+    }
+
+    /// `Date.now()` is available in both windows and workers.
+    fn now_ms(&self) -> u64 {
+        js_sys::Date::now() as u64
+    }
+}
+
+/// Returns the right type of runtime for the current browser because
+/// Firefox and Chrome do not agree on the parent object for Runtime in WebWorkers.
+/// Firefox uses Window and Chrome uses WorkerGlobalScope.
+pub(crate) async fn get_runtime() -> std::result::Result<BrowserRuntime, &'static str> {
+    // try for chrome first and return if found
+    // it should also work if FF switches to using WorkerGlobalScope as they should
+    match js_sys::global().dyn_into::<WorkerGlobalScope>() {
+        Ok(v) => {
+            return Ok(BrowserRuntime::ChromeWorker(v));
+        }
+        Err(e) => {
+            log!("ServiceWorkerGlobalScope unavailable");
+            log!("{:?}", e);
+        }
+    };
+
+    // this is a fallback for Firefox, but it does not make sense why they would use Window in
+    // web workers
+    match web_sys::window() {
+        Some(v) => {
+            return Ok(BrowserRuntime::FireFoxWindow(v));
+        }
+        None => {
+            log!("Window unavailable");
+        }
+    };
+
+    // no runtime was found, which is a serious problem
+    // because all fetch calls require it
+    // TODO: may be worth a retry
+    Err("Missing browser runtime. It's a bug.")
+}
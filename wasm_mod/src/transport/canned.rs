@@ -0,0 +1,184 @@
+use super::{HttpRequest, HttpResponse, Transport};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// A request as it was received by `CannedTransport`
+#[derive(Debug, Clone)]
+pub(crate) struct RecordedRequest {
+    /// GraphQL `operationName`
+    pub operation: String,
+    /// GraphQL `variables` as a JSON string
+    pub variables: String,
+}
+
+/// A response returned for every request that matches the operation and the variables
+struct CannedResponse {
+    operation: String,
+    /// The response is only returned if the variables contain all these strings
+    variables_contain: Vec<String>,
+    status: u16,
+    headers: HashMap<String, String>,
+    body: String,
+    /// How many more times the response can be returned, unlimited if None
+    remaining: Cell<Option<usize>>,
+}
+
+/// Replays canned responses, e.g. from `samples/`, instead of talking to Spotify.
+/// Responses are matched by the GraphQL operation name and optionally by a part of the variables.
+/// Requests without an operation name are matched by the URL without the query string.
+/// Every request is recorded for assertions.
+/// `sleep` returns immediately and only records the duration and moves the fake clock forward.
+#[derive(Default)]
+pub(crate) struct CannedTransport {
+    responses: Vec<CannedResponse>,
+    pub requests: RefCell<Vec<RecordedRequest>>,
+    pub sleeps: RefCell<Vec<Duration>>,
+    /// A fake clock that only moves when `sleep` is called
+    pub clock_ms: Cell<u64>,
+}
+
+impl CannedTransport {
+    /// Returns `body` with HTTP 200 for all requests of the `operation`.
+    pub fn respond(self, operation: &str, body: &str) -> Self {
+        self.respond_with(operation, &[], 200, &[], body)
+    }
+
+    /// Returns `body` with HTTP 200 for requests of the `operation` if their variables contain all the `needles`.
+    pub fn respond_when(self, operation: &str, needles: &[&str], body: &str) -> Self {
+        self.respond_with(operation, needles, 200, &[], body)
+    }
+
+    /// The most flexible version of `respond`.
+    /// Earlier responses take priority over later ones if more than one matches.
+    pub fn respond_with(
+        mut self,
+        operation: &str,
+        variables_contain: &[&str],
+        status: u16,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> Self {
+        self.responses.push(CannedResponse {
+            operation: operation.to_owned(),
+            variables_contain: variables_contain.iter().map(|v| v.to_string()).collect(),
+            status,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_lowercase(), v.to_string()))
+                .collect(),
+            body: body.to_owned(),
+            remaining: Cell::new(None),
+        });
+        self
+    }
+
+    /// Limits how many times the most recently added response can be returned.
+    /// The next matching response is used after that.
+    pub fn times(self, times: usize) -> Self {
+        if let Some(v) = self.responses.last() {
+            v.remaining.set(Some(times));
+        }
+        self
+    }
+
+    /// Appends all responses from `other` after the ones already added, e.g. to override
+    /// some responses of a shared fixture.
+    pub fn then(mut self, other: CannedTransport) -> Self {
+        self.responses.extend(other.responses);
+        self
+    }
+
+    /// Returns all recorded requests for the operation.
+    pub fn requests_for(&self, operation: &str) -> Vec<RecordedRequest> {
+        self.requests
+            .borrow()
+            .iter()
+            .filter(|v| v.operation == operation)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Extracts the operation name and variables from either the query string or the POST payload.
+fn parse_request(request: &HttpRequest) -> RecordedRequest {
+    if let Some(body) = &request.body {
+        let payload = serde_json::from_str::<serde_json::Value>(body).unwrap_or_default();
+        return RecordedRequest {
+            operation: payload["operationName"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned(),
+            variables: payload["variables"].to_string(),
+        };
+    }
+
+    let query = request.url.split_once('?').map(|v| v.1).unwrap_or_default();
+    let mut recorded = RecordedRequest {
+        operation: String::new(),
+        variables: String::new(),
+    };
+    for pair in query.split('&') {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = urlencoding::decode(value)
+            .map(|v| v.into_owned())
+            .unwrap_or_default();
+        match name {
+            "operationName" => recorded.operation = value,
+            "variables" => recorded.variables = value,
+            _ => {}
+        }
+    }
+
+    // requests outside GraphQL, e.g. for the hash manifest, are matched by the URL
+    if recorded.operation.is_empty() {
+        recorded.operation = request
+            .url
+            .split_once('?')
+            .map(|v| v.0)
+            .unwrap_or(request.url)
+            .to_owned();
+    }
+
+    recorded
+}
+
+impl Transport for CannedTransport {
+    async fn fetch(&self, request: HttpRequest<'_>) -> Result<HttpResponse, String> {
+        let recorded = parse_request(&request);
+        self.requests.borrow_mut().push(recorded.clone());
+
+        let resp = self.responses.iter().find(|v| {
+            v.operation == recorded.operation
+                && v.remaining.get() != Some(0)
+                && v.variables_contain
+                    .iter()
+                    .all(|needle| recorded.variables.contains(needle))
+        });
+
+        match resp {
+            Some(v) => {
+                v.remaining.set(v.remaining.get().map(|n| n - 1));
+                Ok(HttpResponse {
+                    status: v.status,
+                    headers: v.headers.clone(),
+                    body: v.body.clone(),
+                })
+            }
+            None => {
+                println!("No canned response for {recorded:?}");
+                Err(format!("No canned response for {}", recorded.operation))
+            }
+        }
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.sleeps.borrow_mut().push(duration);
+        self.clock_ms
+            .set(self.clock_ms.get() + duration.as_millis() as u64);
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.clock_ms.get()
+    }
+}
@@ -0,0 +1,147 @@
+use super::{HttpRequest, HttpResponse, Transport};
+use crate::constants::log;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// All Spotify requests go to this host. It is replaced with `base_url` if one is set.
+const SPOTIFY_HOST: &str = "https://api-partner.spotify.com";
+
+/// Sends requests with a blocking native HTTP client.
+/// Use it outside the browser, e.g. for running the client against a local stand-in server.
+/// It blocks the thread for the duration of the request, which is fine for tests and CLI tools.
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct NativeTransport {
+    /// Replaces `https://api-partner.spotify.com` in all request URLs, e.g. `http://127.0.0.1:8080`
+    base_url: Option<String>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl NativeTransport {
+    /// Sends all requests to the given host instead of Spotify.
+    pub fn with_base_url(base_url: &str) -> Self {
+        Self {
+            base_url: Some(base_url.trim_end_matches('/').to_owned()),
+        }
+    }
+}
+
+impl Transport for NativeTransport {
+    async fn fetch(&self, request: HttpRequest<'_>) -> Result<HttpResponse, String> {
+        let url = match &self.base_url {
+            Some(base_url) => request.url.replacen(SPOTIFY_HOST, base_url, 1),
+            None => request.url.to_owned(),
+        };
+
+        let mut native_request = ureq::request(request.method, &url);
+        for (name, value) in request.headers {
+            native_request = native_request.set(name, value);
+        }
+
+        let resp = match request.body {
+            Some(body) => native_request.send_string(&body),
+            None => native_request.call(),
+        };
+
+        // ureq treats 4xx and 5xx as errors, but they are valid responses for the caller to inspect
+        let resp = match resp {
+            Ok(v) => v,
+            Err(ureq::Error::Status(_, v)) => v,
+            Err(e) => {
+                log!("Spotify request failed");
+                log!("{url}");
+                log!("{:?}", e);
+                return Err(e.to_string());
+            }
+        };
+
+        let status = resp.status();
+        let headers = resp
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                resp.header(&name)
+                    .map(|value| (name.to_lowercase(), value.to_owned()))
+            })
+            .collect::<HashMap<String, String>>();
+
+        let body = match resp.into_string() {
+            Ok(v) => v,
+            Err(e) => {
+                log!("Cannot read Spotify response body");
+                log!("{url}");
+                log!("{:?}", e);
+                return Err(e.to_string());
+            }
+        };
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Blocks the thread because there is nothing else to run while waiting.
+    async fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn now_ms(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|v| v.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serves a single canned HTTP response from a local port and returns the base URL.
+    fn serve_once(status_line: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // read the request head, the body is not needed
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let resp = format!(
+                "{status_line}\r\ncontent-type: application/json\r\nretry-after: 3\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(resp.as_bytes()).unwrap();
+        });
+
+        base_url
+    }
+
+    #[test]
+    fn fetch_from_stand_in_server() {
+        let base_url = serve_once("HTTP/1.1 429 Too Many Requests", r#"{"data":null}"#);
+        let transport = NativeTransport::with_base_url(&base_url);
+
+        let resp = futures::executor::block_on(transport.fetch(HttpRequest {
+            method: "GET",
+            url: "https://api-partner.spotify.com/pathfinder/v1/query?operationName=getAlbum",
+            headers: vec![("accept", "application/json")],
+            body: None,
+        }))
+        .unwrap();
+
+        assert_eq!(resp.status, 429);
+        assert_eq!(
+            resp.headers.get("retry-after").map(String::as_str),
+            Some("3")
+        );
+        assert_eq!(resp.body, r#"{"data":null}"#);
+    }
+}
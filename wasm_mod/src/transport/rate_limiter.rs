@@ -0,0 +1,217 @@
+use super::{HttpRequest, HttpResponse, Transport};
+use crate::constants::log;
+use std::cell::Cell;
+use std::time::Duration;
+
+/// The rate is never lowered below this many requests per second, no matter how many 429s there are
+const MIN_REQUESTS_PER_SECOND: f64 = 0.5;
+
+/// Every successful response raises the lowered rate by this many requests per second
+/// until it is back to the configured rate
+const RATE_RECOVERY_PER_RESPONSE: f64 = 0.1;
+
+/// A token bucket that limits the rate of requests sent via the inner transport
+/// no matter how many of them are in flight.
+/// All requests in the run should share the same instance for the limit to be global.
+///
+/// The bucket holds up to `burst` tokens and is refilled at the current rate.
+/// Every request takes a token or waits until there is one.
+/// The rate is halved on every 429 response and slowly goes back up with every successful response.
+pub(crate) struct RateLimiter<T: Transport> {
+    inner: T,
+    /// The configured rate in requests per second
+    max_rate: f64,
+    /// The current rate in requests per second, lowered after 429s
+    rate: Cell<f64>,
+    /// The maximum number of tokens in the bucket
+    burst: f64,
+    /// Negative if some requests are waiting for the tokens to be refilled
+    tokens: Cell<f64>,
+    /// When the tokens were last refilled, None before the first request
+    refilled_at_ms: Cell<Option<u64>>,
+    /// No requests are sent until then if Spotify asked to retry after a certain time
+    paused_until_ms: Cell<u64>,
+}
+
+impl<T: Transport> RateLimiter<T> {
+    /// * requests_per_second - the sustained rate
+    /// * burst - how many requests can be sent at once after a quiet period
+    pub fn new(inner: T, requests_per_second: u32, burst: u32) -> Self {
+        let rate = f64::from(requests_per_second).max(MIN_REQUESTS_PER_SECOND);
+        let burst = f64::from(burst.max(1));
+        Self {
+            inner,
+            max_rate: rate,
+            rate: Cell::new(rate),
+            burst,
+            tokens: Cell::new(burst),
+            refilled_at_ms: Cell::new(None),
+            paused_until_ms: Cell::new(0),
+        }
+    }
+
+    /// Adds the tokens accumulated since the last refill at the current rate.
+    fn refill(&self, now_ms: u64) {
+        let elapsed_ms = match self.refilled_at_ms.get() {
+            Some(v) => now_ms.saturating_sub(v),
+            None => 0,
+        };
+        self.refilled_at_ms.set(Some(now_ms));
+
+        let tokens = self.tokens.get() + elapsed_ms as f64 * self.rate.get() / 1000.0;
+        self.tokens.set(tokens.min(self.burst));
+    }
+
+    /// Takes a token and returns how long to wait before sending the request.
+    /// The token is taken before waiting so that concurrent requests queue up behind each other.
+    fn take_token(&self, now_ms: u64) -> u64 {
+        self.refill(now_ms);
+
+        let tokens = self.tokens.get() - 1.0;
+        self.tokens.set(tokens);
+
+        let wait_for_token_ms = if tokens < 0.0 {
+            (-tokens * 1000.0 / self.rate.get()).ceil() as u64
+        } else {
+            0
+        };
+        let wait_for_pause_ms = self.paused_until_ms.get().saturating_sub(now_ms);
+
+        wait_for_token_ms.max(wait_for_pause_ms)
+    }
+
+    /// Adjusts the rate to what Spotify is happy with.
+    fn adapt(&self, response: &HttpResponse, now_ms: u64) {
+        if response.status == 429 {
+            let rate = (self.rate.get() / 2.0).max(MIN_REQUESTS_PER_SECOND);
+            log!("Rate limited by Spotify, slowing down to {rate:.1} requests per second");
+            self.rate.set(rate);
+
+            // make everyone wait if Spotify said for how long
+            if let Some(retry_after) = response
+                .headers
+                .get("retry-after")
+                .and_then(|v| v.trim().parse::<u64>().ok())
+            {
+                let paused_until_ms = now_ms + retry_after * 1000;
+                self.paused_until_ms
+                    .set(self.paused_until_ms.get().max(paused_until_ms));
+            }
+        } else if (200..300).contains(&response.status) && self.rate.get() < self.max_rate {
+            self.rate
+                .set((self.rate.get() + RATE_RECOVERY_PER_RESPONSE).min(self.max_rate));
+        }
+    }
+}
+
+impl<T: Transport> Transport for RateLimiter<T> {
+    /// Waits for a token before sending the request.
+    async fn fetch(&self, request: HttpRequest<'_>) -> Result<HttpResponse, String> {
+        let wait_ms = self.take_token(self.inner.now_ms());
+        if wait_ms > 0 {
+            self.inner.sleep(Duration::from_millis(wait_ms)).await;
+        }
+
+        let resp = self.inner.fetch(request).await;
+        if let Ok(resp) = &resp {
+            self.adapt(resp, self.inner.now_ms());
+        }
+
+        resp
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        self.inner.sleep(duration).await
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.inner.now_ms()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::CannedTransport;
+    use futures::executor::block_on;
+
+    fn fetch(limiter: &RateLimiter<CannedTransport>) {
+        block_on(limiter.fetch(HttpRequest {
+            method: "GET",
+            url: "https://api-partner.spotify.com/pathfinder/v1/query?operationName=getAlbum",
+            headers: Vec::new(),
+            body: None,
+        }))
+        .unwrap();
+    }
+
+    /// Returns the waits in ms since the last call and clears them.
+    fn take_sleeps(limiter: &RateLimiter<CannedTransport>) -> Vec<u64> {
+        limiter
+            .inner
+            .sleeps
+            .borrow_mut()
+            .drain(..)
+            .map(|v| v.as_millis() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn burst_then_steady_rate() {
+        let limiter = RateLimiter::new(CannedTransport::default().respond("getAlbum", "{}"), 4, 2);
+
+        for _ in 0..4 {
+            fetch(&limiter);
+        }
+
+        // the first 2 go right away, the rest wait for a token at 4 per second
+        assert_eq!(take_sleeps(&limiter), vec![250, 250]);
+        assert_eq!(limiter.now_ms(), 500);
+    }
+
+    #[test]
+    fn refill_after_quiet_period() {
+        let limiter = RateLimiter::new(CannedTransport::default().respond("getAlbum", "{}"), 4, 2);
+        for _ in 0..3 {
+            fetch(&limiter);
+        }
+        take_sleeps(&limiter);
+
+        // the bucket is full again after a long pause, but not over the burst size
+        limiter.inner.clock_ms.set(limiter.now_ms() + 10_000);
+        for _ in 0..3 {
+            fetch(&limiter);
+        }
+
+        assert_eq!(take_sleeps(&limiter), vec![250]);
+    }
+
+    #[test]
+    fn slow_down_on_429_and_recover() {
+        let limiter = RateLimiter::new(
+            CannedTransport::default()
+                .respond_with("getAlbum", &[], 429, &[("Retry-After", "3")], "")
+                .times(1)
+                .respond("getAlbum", "{}"),
+            4,
+            1,
+        );
+
+        // the 429 halves the rate and pauses everyone for 3 seconds
+        fetch(&limiter);
+        fetch(&limiter);
+        assert_eq!(take_sleeps(&limiter), vec![3000]);
+        assert!((limiter.rate.get() - 2.1).abs() < 1e-9);
+
+        // the bucket refilled during the pause, but the next token takes 1/2.2 of a second
+        fetch(&limiter);
+        fetch(&limiter);
+        assert_eq!(take_sleeps(&limiter), vec![455]);
+
+        // every successful response brings the rate back up to the configured 4
+        for _ in 0..30 {
+            fetch(&limiter);
+        }
+        assert_eq!(limiter.rate.get(), 4.0);
+    }
+}